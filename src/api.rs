@@ -0,0 +1,207 @@
+//! Functions other plugins can look up and call.
+
+use crate::plugin::{self, model};
+use classicube_sys::{cc_bool, Entity, Matrix};
+use log::*;
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_void},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    slice,
+};
+
+/// Called with each model's name and the `obj` given to `BlockbenchLoader_ListModels`.
+pub type ListModelsCallback = extern "C" fn(name: *const c_char, obj: *mut c_void);
+
+/// Runs `f`, returning `failed` instead if it panics,
+/// since unwinding into the caller's C code is undefined behavior.
+fn guard<T>(function: &str, failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        error!("{} panicked", function);
+        failed
+    })
+}
+
+/// Parses the .bbmodel in `data` and registers it as `name`,
+/// replacing the model if `name` was loaded before.
+///
+/// Returns false if it couldn't be loaded, the reason is logged.
+#[no_mangle]
+pub unsafe extern "C" fn BlockbenchLoader_LoadModel(
+    name: *const c_char,
+    data: *const u8,
+    length: c_int,
+) -> cc_bool {
+    guard("BlockbenchLoader_LoadModel", 0, || {
+        if name.is_null() || data.is_null() || length < 0 {
+            return 0;
+        }
+
+        let name = CStr::from_ptr(name).to_string_lossy();
+        let data = slice::from_raw_parts(data, length as usize);
+
+        match plugin::load_model(&name, data) {
+            Ok(()) => 1,
+            Err(e) => {
+                error!("{:?}: {}", name, e);
+                0
+            }
+        }
+    })
+}
+
+/// Loads the .bbmodel file at `path`, with its sidecar file if there is one,
+/// as `name` or after the file if `name` is null.
+#[no_mangle]
+pub unsafe extern "C" fn BlockbenchLoader_LoadModelFile(
+    name: *const c_char,
+    path: *const c_char,
+) -> cc_bool {
+    guard("BlockbenchLoader_LoadModelFile", 0, || {
+        if path.is_null() {
+            return 0;
+        }
+
+        let name = if name.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(name).to_string_lossy())
+        };
+        let path = CStr::from_ptr(path).to_string_lossy();
+
+        match plugin::load_model_file(name.as_deref(), Path::new(&*path)) {
+            Ok(()) => 1,
+            Err(e) => {
+                error!("{:?}: {}", path, e);
+                0
+            }
+        }
+    })
+}
+
+/// Unregisters the model `name`, entities using it go back to humanoid.
+///
+/// Returns false if there's no such model.
+#[no_mangle]
+pub unsafe extern "C" fn BlockbenchLoader_UnregisterModel(name: *const c_char) -> cc_bool {
+    guard("BlockbenchLoader_UnregisterModel", 0, || {
+        if name.is_null() {
+            return 0;
+        }
+
+        let name = CStr::from_ptr(name).to_string_lossy();
+        plugin::unregister(&name) as cc_bool
+    })
+}
+
+/// Calls `callback` with the name of each of our models, returning how many there are.
+///
+/// `callback` may be null to only count them. Returns -1 if something went wrong.
+#[no_mangle]
+pub unsafe extern "C" fn BlockbenchLoader_ListModels(
+    callback: Option<ListModelsCallback>,
+    obj: *mut c_void,
+) -> c_int {
+    guard("BlockbenchLoader_ListModels", -1, || {
+        let names = model::names();
+
+        if let Some(callback) = callback {
+            for name in &names {
+                if let Ok(name) = CString::new(name.as_str()) {
+                    callback(name.as_ptr(), obj);
+                }
+            }
+        }
+
+        names.len() as c_int
+    })
+}
+
+/// Plays the Blockbench animation `name`, or an alias for it from the model's sidecar,
+/// on the entity with id `entity_id`, from the start even if it's already playing.
+///
+/// Returns false if the entity's model isn't ours or has no such animation.
+#[no_mangle]
+pub unsafe extern "C" fn BlockbenchLoader_PlayAnimation(
+    entity_id: u8,
+    name: *const c_char,
+) -> cc_bool {
+    guard("BlockbenchLoader_PlayAnimation", 0, || {
+        if name.is_null() {
+            return 0;
+        }
+
+        let name = CStr::from_ptr(name).to_string_lossy();
+        model::play_animation(entity_id, &name) as cc_bool
+    })
+}
+
+/// Stops any animation on the entity with id `entity_id`, putting it back at rest.
+#[no_mangle]
+pub unsafe extern "C" fn BlockbenchLoader_StopAnimation(entity_id: u8) {
+    guard("BlockbenchLoader_StopAnimation", (), || {
+        model::animation::stop(entity_id);
+    })
+}
+
+/// Sets `transform` to where the attachment point `name` of `entity`'s model
+/// is in the world, made from a Blockbench locator or null object.
+///
+/// Returns false if the entity isn't using one of our models or it has no such point.
+#[no_mangle]
+pub unsafe extern "C" fn BlockbenchLoader_GetAttachmentPoint(
+    entity: *mut Entity,
+    name: *const c_char,
+    transform: *mut Matrix,
+) -> cc_bool {
+    guard("BlockbenchLoader_GetAttachmentPoint", 0, || {
+        if entity.is_null() || name.is_null() || transform.is_null() {
+            return 0;
+        }
+
+        let name = CStr::from_ptr(name).to_string_lossy();
+
+        if let Some(found) = model::attachment_transform(&*entity, &name) {
+            *transform = found.to_matrix();
+            1
+        } else {
+            0
+        }
+    })
+}
+
+/// Tints `group` of the model of the entity with id `entity_id`,
+/// or the whole model if `group` is null.
+#[no_mangle]
+pub unsafe extern "C" fn BlockbenchLoader_SetTint(
+    entity_id: u8,
+    group: *const c_char,
+    r: u8,
+    g: u8,
+    b: u8,
+) {
+    guard("BlockbenchLoader_SetTint", (), || {
+        let group = if group.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(group).to_string_lossy())
+        };
+
+        model::tint::set_tint(entity_id, group.as_deref(), [r, g, b]);
+    })
+}
+
+/// Removes all tints from the entity with id `entity_id`.
+#[no_mangle]
+pub unsafe extern "C" fn BlockbenchLoader_ClearTint(entity_id: u8) {
+    guard("BlockbenchLoader_ClearTint", (), || {
+        model::tint::clear_tints(entity_id);
+    })
+}
+
+#[test]
+fn test_guard() {
+    assert_eq!(guard("test", 0, || 1), 1);
+    assert_eq!(guard("test", -1, || panic!("oops")), -1);
+}
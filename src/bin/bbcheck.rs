@@ -0,0 +1,53 @@
+//! Checks .bbmodel files before they're given to the game.
+//!
+//! Prints every problem in each file and what's in it,
+//! exiting with 1 if any file has errors.
+//!
+//! Doesn't need the game, build it on its own with
+//! `cargo build --bin bbcheck --no-default-features`.
+
+use classicube_blockbench_loader::validate::{self, Severity};
+use std::{env, fs, process};
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: bbcheck <file.bbmodel>...");
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        println!("{}", path);
+
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                println!("  error: {}", e);
+                failed = true;
+                continue;
+            }
+        };
+
+        let (problems, summary) = validate::check(&data);
+        for problem in &problems {
+            println!("  {}", problem);
+        }
+        if let Some(summary) = summary {
+            print!("{}", summary);
+        }
+
+        let errors = problems
+            .iter()
+            .filter(|problem| problem.severity == Severity::Error)
+            .count();
+        let warnings = problems.len() - errors;
+        println!("  {} errors, {} warnings", errors, warnings);
+
+        failed |= errors > 0;
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
@@ -4,10 +4,14 @@ pub use error_chain::{bail, ensure};
 error_chain! {
     foreign_links {
         Fmt(::std::fmt::Error);
+        Io(::std::io::Error);
         ParseIntError(::std::num::ParseIntError);
         ParseFloatError(::std::num::ParseFloatError);
+        Utf8(::std::str::Utf8Error);
         SerdeJson(serde_json::Error);
         Base64(base64::DecodeError);
         Png(png::DecodingError);
+        Toml(toml::de::Error);
+        Zip(zip::result::ZipError);
     }
 }
@@ -1,27 +1,51 @@
+#![cfg_attr(not(feature = "plugin"), allow(dead_code))]
+
+#[cfg(feature = "plugin")]
+mod api;
 mod error;
+#[cfg(feature = "plugin")]
 mod logger;
 mod plugin;
 
+pub use self::plugin::validate;
+
+#[cfg(feature = "plugin")]
 use classicube_sys::*;
+#[cfg(feature = "plugin")]
 use log::*;
+#[cfg(feature = "plugin")]
 use std::{os::raw::c_int, ptr};
 
+#[cfg(feature = "plugin")]
 extern "C" fn init() {
-    logger::initialize(true, false);
-    debug!("blockbench-loader init");
-
+    // sets up logging from the config first
     plugin::init();
 }
 
+#[cfg(feature = "plugin")]
 extern "C" fn free() {
     debug!("blockbench-loader free");
 
     plugin::free();
 }
 
+#[cfg(feature = "plugin")]
+extern "C" fn reset() {
+    debug!("blockbench-loader reset");
+
+    plugin::reset();
+}
+
+#[cfg(feature = "plugin")]
+extern "C" fn on_new_map_loaded() {
+    plugin::on_new_map_loaded();
+}
+
+#[cfg(feature = "plugin")]
 #[no_mangle]
 pub static Plugin_ApiVersion: c_int = 1;
 
+#[cfg(feature = "plugin")]
 #[no_mangle]
 pub static mut Plugin_Component: IGameComponent = IGameComponent {
     // Called when the game is being loaded.
@@ -29,11 +53,11 @@ pub static mut Plugin_Component: IGameComponent = IGameComponent {
     // Called when the component is being freed. (e.g. due to game being closed)
     Free: Some(free),
     // Called to reset the component's state. (e.g. reconnecting to server)
-    Reset: None,
+    Reset: Some(reset),
     // Called to update the component's state when the user begins loading a new map.
     OnNewMap: None,
     // Called to update the component's state when the user has finished loading a new map.
-    OnNewMapLoaded: None,
+    OnNewMapLoaded: Some(on_new_map_loaded),
     // Next component in linked list of components.
     next: ptr::null_mut(),
 };
@@ -1,16 +1,10 @@
 use simplelog::*;
-use std::sync::Once;
+use std::{fs::File, path::Path, sync::Once};
 
-pub fn initialize(debug: bool, other_crates: bool) {
+pub fn initialize(level: LevelFilter, log_file: Option<&Path>, other_crates: bool) {
     static START: Once = Once::new();
 
     START.call_once(move || {
-        let level = if debug {
-            LevelFilter::Debug
-        } else {
-            LevelFilter::Info
-        };
-
         let my_crate_name = env!("CARGO_PKG_NAME").replace("-", "_");
 
         let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::with_capacity(2);
@@ -24,7 +18,16 @@ pub fn initialize(debug: bool, other_crates: bool) {
             config.add_filter_allow(my_crate_name);
         }
 
-        loggers.push(TermLogger::new(level, config.build(), TerminalMode::Mixed));
+        let config = config.build();
+
+        loggers.push(TermLogger::new(level, config.clone(), TerminalMode::Mixed));
+
+        if let Some(log_file) = log_file {
+            match File::create(log_file) {
+                Ok(file) => loggers.push(WriteLogger::new(level, config, file)),
+                Err(e) => eprintln!("couldn't create log file {:?}: {}", log_file, e),
+            }
+        }
 
         CombinedLogger::init(loggers).unwrap();
     });
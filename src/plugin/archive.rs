@@ -0,0 +1,131 @@
+//! Models packed into .zip files, with their sidecars.
+
+use super::sidecar::{self, Sidecar};
+use crate::error::*;
+use log::*;
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use zip::ZipArchive;
+
+pub struct ArchivedModel {
+    /// path inside the archive without the extension, like "mobs/zombie"
+    pub name: String,
+
+    /// the archive's path joined with the model's, to tell where it came from
+    pub source: PathBuf,
+
+    pub data: Vec<u8>,
+    pub sidecar: Option<Sidecar>,
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("zip"))
+}
+
+/// Every .bbmodel in the archive at `path`, or only those inside `folder` if given.
+pub fn read_models(path: &Path, folder: Option<&str>) -> Result<Vec<ArchivedModel>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+
+    // keyed by entry name relative to `folder`
+    let mut entries = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+
+        let name = entry.name().replace('\\', "/");
+        let name = match folder {
+            Some(folder) => match name.strip_prefix(&format!("{}/", folder)) {
+                Some(name) => name.to_string(),
+                None => continue,
+            },
+            None => name,
+        };
+
+        let extension = Path::new(&name).extension().and_then(|e| e.to_str());
+        if extension == Some("bbmodel") || extension.map(is_sidecar_extension).unwrap_or(false) {
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut data)?;
+            entries.insert(name, data);
+        }
+    }
+
+    let mut models = Vec::new();
+    for (name, data) in &entries {
+        let stem = match name.strip_suffix(".bbmodel") {
+            Some(stem) => stem,
+            None => continue,
+        };
+
+        let mut sidecar = None;
+        for extension in &sidecar::EXTENSIONS {
+            let sidecar_name = format!("{}.{}", stem, extension);
+            if let Some(sidecar_data) = entries.get(&sidecar_name) {
+                let parsed = String::from_utf8(sidecar_data.clone())
+                    .chain_err(|| "sidecar isn't utf-8")
+                    .and_then(|text| Sidecar::parse(&text, *extension == "json"));
+
+                match parsed {
+                    Ok(parsed) => sidecar = Some(parsed),
+                    Err(e) => warn!("{:?} {:?}: {}", path, sidecar_name, e),
+                }
+                break;
+            }
+        }
+
+        models.push(ArchivedModel {
+            name: stem.to_string(),
+            source: path.join(name),
+            data: data.clone(),
+            sidecar,
+        });
+    }
+
+    Ok(models)
+}
+
+fn is_sidecar_extension(extension: &str) -> bool {
+    sidecar::EXTENSIONS.contains(&extension)
+}
+
+#[test]
+fn test_read_models() {
+    use std::io::Write;
+    use zip::{write::FileOptions, ZipWriter};
+
+    let path = std::env::temp_dir().join("blockbench-loader-test-archive.zip");
+    {
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for (name, data) in &[
+            ("npc.bbmodel", "npc"),
+            ("npc.toml", "scale = 2.0"),
+            ("mobs/slime.bbmodel", "slime"),
+            ("blockbench/mobs/zombie.bbmodel", "zombie"),
+            ("textures/terrain.png", ""),
+        ] {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    let models = read_models(&path, None).unwrap();
+    let names: Vec<&str> = models.iter().map(|model| model.name.as_str()).collect();
+    assert_eq!(names, vec!["blockbench/mobs/zombie", "mobs/slime", "npc"]);
+    assert_eq!(models[2].data, b"npc");
+    assert_eq!(models[2].sidecar.as_ref().unwrap().scale, Some(2.0));
+    assert_eq!(models[2].source, path.join("npc.bbmodel"));
+
+    // like a texture pack
+    let models = read_models(&path, Some("blockbench")).unwrap();
+    let names: Vec<&str> = models.iter().map(|model| model.name.as_str()).collect();
+    assert_eq!(names, vec!["mobs/zombie"]);
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,404 @@
+//! A compact form of a parsed `BBModel` for the disk cache, so reading it back
+//! doesn't go through json at all.
+//!
+//! Everything is little endian, lengths are `u32`s and enums are a `u8` tag.
+
+use super::json::*;
+use crate::error::*;
+use std::{collections::BTreeMap, convert::TryInto, os::raw::c_float};
+
+pub trait Binary: Sized {
+    fn write(&self, out: &mut Vec<u8>);
+    fn read(input: &mut &[u8]) -> Result<Self>;
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    ensure!(input.len() >= len, "unexpected end of data");
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+macro_rules! binary_number {
+    ($($ty:ty),*) => {
+        $(
+            impl Binary for $ty {
+                fn write(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read(input: &mut &[u8]) -> Result<Self> {
+                    let bytes = take(input, std::mem::size_of::<$ty>())?;
+                    Ok(<$ty>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+binary_number!(u8, u16, u32, c_float);
+
+impl Binary for bool {
+    fn write(&self, out: &mut Vec<u8>) {
+        (*self as u8).write(out);
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        match u8::read(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => bail!("bad bool {}", other),
+        }
+    }
+}
+
+impl Binary for usize {
+    fn write(&self, out: &mut Vec<u8>) {
+        (*self as u32).write(out);
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        Ok(u32::read(input)? as usize)
+    }
+}
+
+impl Binary for String {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.len().write(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        let len = usize::read(input)?;
+        let bytes = take(input, len)?;
+        Ok(std::str::from_utf8(bytes)?.to_string())
+    }
+}
+
+impl<T: Binary> Binary for Vec<T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.len().write(out);
+        for item in self {
+            item.write(out);
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        let len = usize::read(input)?;
+        // every item is at least a byte, so a bad length can't allocate much
+        ensure!(len <= input.len(), "unexpected end of data");
+        (0..len).map(|_| T::read(input)).collect()
+    }
+}
+
+impl<T: Binary> Binary for Option<T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            None => false.write(out),
+            Some(value) => {
+                true.write(out);
+                value.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        Ok(if bool::read(input)? {
+            Some(T::read(input)?)
+        } else {
+            None
+        })
+    }
+}
+
+impl<T: Binary + Copy + Default, const N: usize> Binary for [T; N] {
+    fn write(&self, out: &mut Vec<u8>) {
+        for item in self {
+            item.write(out);
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        let mut array = [T::default(); N];
+        for item in &mut array {
+            *item = T::read(input)?;
+        }
+        Ok(array)
+    }
+}
+
+impl<T: Binary> Binary for BTreeMap<String, T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.len().write(out);
+        for (key, value) in self {
+            key.write(out);
+            value.write(out);
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        let len = usize::read(input)?;
+        ensure!(len <= input.len(), "unexpected end of data");
+        (0..len)
+            .map(|_| Ok((String::read(input)?, T::read(input)?)))
+            .collect()
+    }
+}
+
+/// Writes the fields in the order they're listed.
+macro_rules! binary_struct {
+    ($ty:ident { $($field:ident),* $(,)? }) => {
+        impl Binary for $ty {
+            fn write(&self, out: &mut Vec<u8>) {
+                $(self.$field.write(out);)*
+            }
+
+            fn read(input: &mut &[u8]) -> Result<Self> {
+                Ok(Self {
+                    $($field: Binary::read(input)?,)*
+                })
+            }
+        }
+    };
+}
+
+/// Enums without fields, tagged by their position in the list.
+macro_rules! binary_enum {
+    ($ty:ident { $($variant:ident),* $(,)? }) => {
+        impl Binary for $ty {
+            fn write(&self, out: &mut Vec<u8>) {
+                let variants = [$($ty::$variant),*];
+                let tag = variants.iter().position(|variant| variant == self).unwrap();
+                (tag as u8).write(out);
+            }
+
+            fn read(input: &mut &[u8]) -> Result<Self> {
+                let tag = u8::read(input)?;
+                let variants = [$($ty::$variant),*];
+                variants
+                    .get(tag as usize)
+                    .cloned()
+                    .chain_err(|| format!("bad {} {}", stringify!($ty), tag))
+            }
+        }
+    };
+}
+
+binary_struct!(BBModel {
+    meta,
+    name,
+    resolution,
+    elements,
+    outliner,
+    textures,
+    animations,
+    blockbench_loader,
+});
+binary_struct!(LoaderProperties { scale, offset });
+binary_struct!(Meta {
+    box_uv,
+    model_format
+});
+binary_enum!(ModelFormat {
+    Free,
+    Bedrock,
+    JavaBlock
+});
+binary_struct!(Texture {
+    name,
+    id,
+    particle,
+    mode,
+    uuid,
+    render_mode,
+    source,
+    frame_time,
+    frame_order_type,
+    frame_order,
+});
+binary_enum!(FrameOrderType {
+    Loop,
+    Backwards,
+    BackAndForth,
+    Custom
+});
+binary_enum!(TextureMode { Bitmap });
+binary_enum!(RenderMode {
+    Default,
+    Emissive,
+    Additive,
+    Layered
+});
+binary_struct!(Resolution { width, height });
+binary_struct!(Cube {
+    name,
+    from,
+    to,
+    autouv,
+    color,
+    rotation,
+    rescale,
+    origin,
+    uv_offset,
+    mirror_uv,
+    faces,
+    uuid,
+});
+binary_struct!(Faces {
+    north,
+    east,
+    south,
+    west,
+    up,
+    down
+});
+binary_struct!(Face {
+    uv,
+    rotation,
+    texture
+});
+binary_struct!(Locator {
+    name,
+    position,
+    rotation,
+    uuid
+});
+binary_struct!(Group {
+    name,
+    origin,
+    children,
+    uuid
+});
+binary_struct!(Mesh {
+    name,
+    origin,
+    rotation,
+    vertices,
+    faces,
+    uuid,
+});
+binary_struct!(MeshFace {
+    vertices,
+    uv,
+    texture
+});
+binary_struct!(Animation {
+    name,
+    loop_mode,
+    length,
+    animators
+});
+binary_enum!(LoopMode { Once, Hold, Loop });
+binary_struct!(Animator { name, keyframes });
+binary_struct!(Keyframe {
+    channel,
+    data_points,
+    time,
+    interpolation,
+});
+binary_struct!(DataPoint { x, y, z });
+
+impl Binary for Element {
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Element::Cube(e) => {
+                0u8.write(out);
+                e.write(out);
+            }
+            Element::Mesh(e) => {
+                1u8.write(out);
+                e.write(out);
+            }
+            Element::Locator(e) => {
+                2u8.write(out);
+                e.write(out);
+            }
+            Element::NullObject(e) => {
+                3u8.write(out);
+                e.write(out);
+            }
+            Element::Unknown(kind) => {
+                4u8.write(out);
+                kind.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        Ok(match u8::read(input)? {
+            0 => Element::Cube(Binary::read(input)?),
+            1 => Element::Mesh(Binary::read(input)?),
+            2 => Element::Locator(Binary::read(input)?),
+            3 => Element::NullObject(Binary::read(input)?),
+            4 => Element::Unknown(Binary::read(input)?),
+            other => bail!("bad Element {}", other),
+        })
+    }
+}
+
+impl Binary for Outliner {
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Outliner::Element(uuid) => {
+                0u8.write(out);
+                uuid.write(out);
+            }
+            Outliner::Group(group) => {
+                1u8.write(out);
+                group.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        Ok(match u8::read(input)? {
+            0 => Outliner::Element(Binary::read(input)?),
+            1 => Outliner::Group(Binary::read(input)?),
+            other => bail!("bad Outliner {}", other),
+        })
+    }
+}
+
+impl Binary for Molang {
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Molang::Number(number) => {
+                0u8.write(out);
+                number.write(out);
+            }
+            Molang::Expression(expression) => {
+                1u8.write(out);
+                expression.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self> {
+        Ok(match u8::read(input)? {
+            0 => Molang::Number(Binary::read(input)?),
+            1 => Molang::Expression(Binary::read(input)?),
+            other => bail!("bad Molang {}", other),
+        })
+    }
+}
+
+#[test]
+fn test_binary() {
+    for path in &["tests/Player.bbmodel", "tests/mesh.bbmodel"] {
+        let data = std::fs::read(path).unwrap();
+        let bb: BBModel = serde_json::from_slice(&data).unwrap();
+
+        let mut out = Vec::new();
+        bb.write(&mut out);
+
+        let mut input = &out[..];
+        let read = BBModel::read(&mut input).unwrap();
+        assert!(input.is_empty());
+        assert_eq!(format!("{:?}", read), format!("{:?}", bb));
+
+        // cut short anywhere is an error, not a panic
+        for len in (0..out.len()).step_by(7) {
+            assert!(BBModel::read(&mut &out[..len]).is_err());
+        }
+    }
+}
@@ -1,30 +1,54 @@
-use serde::{Deserialize, Serialize};
-use std::os::raw::c_float;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{collections::BTreeMap, os::raw::c_float};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BBModel {
     pub meta: Meta,
     pub name: String,
     pub resolution: Resolution,
     pub elements: Vec<Element>,
-    // outliner: Vec<Outline>,
+    #[serde(default)]
+    pub outliner: Vec<Outliner>,
     pub textures: Vec<Texture>,
+
+    #[serde(default)]
+    pub animations: Vec<Animation>,
+
+    /// our own settings, added by hand since Blockbench has nowhere for them
+    #[serde(default)]
+    pub blockbench_loader: Option<LoaderProperties>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoaderProperties {
+    #[serde(default = "default_scale")]
+    pub scale: c_float,
+
+    /// in pixels, like everything else in the file
+    #[serde(default)]
+    pub offset: [c_float; 3],
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn default_scale() -> c_float {
+    1.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Meta {
     pub box_uv: bool,
     pub model_format: ModelFormat,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum ModelFormat {
     Free,
     Bedrock,
+    #[serde(rename = "java_block")]
+    JavaBlock,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Texture {
     pub name: String,
     pub id: String,
@@ -32,29 +56,113 @@ pub struct Texture {
     pub mode: TextureMode,
     pub uuid: String,
 
+    #[serde(default)]
+    pub render_mode: RenderMode,
+
     /// base64 data url
     pub source: String,
+
+    /// ticks each frame of an animated texture is shown for
+    #[serde(default = "default_frame_time")]
+    pub frame_time: u32,
+
+    #[serde(default)]
+    pub frame_order_type: FrameOrderType,
+
+    /// frame numbers separated by spaces, used with `FrameOrderType::Custom`
+    #[serde(default)]
+    pub frame_order: String,
+}
+
+fn default_frame_time() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameOrderType {
+    #[default]
+    Loop,
+    Backwards,
+    BackAndForth,
+    Custom,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum TextureMode {
     Bitmap,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// blockbench 4's texture render modes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderMode {
+    #[default]
+    Default,
+    Emissive,
+    Additive,
+    Layered,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Resolution {
     pub width: usize,
     pub height: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Element {
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Element {
+    Cube(Cube),
+    Mesh(Mesh),
+    Locator(Locator),
+    NullObject(Locator),
+
+    /// some `type` we don't know about
+    Unknown(String),
+}
+
+impl<'de> Deserialize<'de> for Element {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+
+        // blockbench 3 only had cubes and didn't write a type
+        let kind = value
+            .get("type")
+            .and_then(|kind| kind.as_str())
+            .unwrap_or("cube")
+            .to_string();
+
+        match kind.as_str() {
+            "cube" => serde_json::from_value(value)
+                .map(Element::Cube)
+                .map_err(de::Error::custom),
+            "mesh" => serde_json::from_value(value)
+                .map(Element::Mesh)
+                .map_err(de::Error::custom),
+            "locator" => serde_json::from_value(value)
+                .map(Element::Locator)
+                .map_err(de::Error::custom),
+            "null_object" => serde_json::from_value(value)
+                .map(Element::NullObject)
+                .map_err(de::Error::custom),
+            _ => Ok(Element::Unknown(kind)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cube {
     pub name: String,
     pub from: [c_float; 3],
     pub to: [c_float; 3],
 
-    // so far only 0?
+    /// 0 uses `faces` as is, 1 resizes them to the cube, 2 maps them from the cube's position
     pub autouv: usize,
 
     // some kind of index?
@@ -64,15 +172,23 @@ pub struct Element {
     // pub locked: bool,
     pub rotation: Option<[c_float; 3]>,
 
+    /// Java block models only, stretch to keep spanning the block when rotated
+    #[serde(default)]
+    pub rescale: bool,
+
     /// "Pivot Point"
     pub origin: [c_float; 3],
 
-    pub uv_offset: Option<[usize; 2]>,
+    pub uv_offset: Option<[c_float; 2]>,
+
+    /// box uv only, flips the texture horizontally
+    #[serde(default)]
+    pub mirror_uv: bool,
     pub faces: Faces,
     pub uuid: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Faces {
     pub north: Face,
     pub east: Face,
@@ -82,12 +198,141 @@ pub struct Faces {
     pub down: Face,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Face {
-    pub uv: [usize; 4],
+    pub uv: [c_float; 4],
+
+    /// clockwise, in degrees
+    #[serde(default)]
+    pub rotation: u16,
+
     pub texture: usize,
 }
 
+/// A named point, also used for null objects which just don't have a rotation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Locator {
+    pub name: String,
+    pub position: [c_float; 3],
+    #[serde(default)]
+    pub rotation: [c_float; 3],
+    pub uuid: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Outliner {
+    /// uuid of an element
+    Element(String),
+    Group(Group),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+
+    /// "Pivot Point"
+    pub origin: [c_float; 3],
+    pub children: Vec<Outliner>,
+    pub uuid: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mesh {
+    pub name: String,
+
+    /// "Pivot Point", vertices are relative to this
+    pub origin: [c_float; 3],
+    pub rotation: Option<[c_float; 3]>,
+
+    pub vertices: BTreeMap<String, [c_float; 3]>,
+    pub faces: BTreeMap<String, MeshFace>,
+    pub uuid: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshFace {
+    /// keys into `Mesh::vertices`, not necessarily in winding order
+    pub vertices: Vec<String>,
+
+    /// per-vertex uv, keyed like `vertices`
+    pub uv: BTreeMap<String, [c_float; 2]>,
+    pub texture: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Animation {
+    pub name: String,
+
+    #[serde(rename = "loop", default)]
+    pub loop_mode: LoopMode,
+
+    /// in seconds
+    pub length: c_float,
+
+    /// keyed by group uuid
+    #[serde(default)]
+    pub animators: BTreeMap<String, Animator>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopMode {
+    #[default]
+    Once,
+    Hold,
+    Loop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Animator {
+    pub name: String,
+
+    #[serde(default)]
+    pub keyframes: Vec<Keyframe>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// "rotation", "position" or "scale", others are sounds and such
+    pub channel: String,
+
+    pub data_points: Vec<DataPoint>,
+
+    /// in seconds
+    pub time: c_float,
+
+    /// "linear", "step", "catmullrom" or "bezier"
+    #[serde(default)]
+    pub interpolation: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataPoint {
+    pub x: Molang,
+    pub y: Molang,
+    pub z: Molang,
+}
+
+/// Usually a number, sometimes as a string, sometimes a real expression.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Molang {
+    Number(c_float),
+    Expression(String),
+}
+
+impl Molang {
+    /// `None` for expressions that aren't just a number.
+    pub fn value(&self) -> Option<c_float> {
+        match self {
+            Molang::Number(number) => Some(*number),
+            Molang::Expression(expression) if expression.trim().is_empty() => Some(0.0),
+            Molang::Expression(expression) => expression.trim().parse().ok(),
+        }
+    }
+}
+
 #[test]
 fn test_json() {
     use std::fs::File;
@@ -1,116 +1,450 @@
+pub mod binary;
 pub mod json;
+pub mod validate;
 
-use self::json::BBModel;
-use super::{model::Cube, Model};
+use self::{
+    binary::Binary,
+    json::{BBModel, Element, FrameOrderType, Group, Outliner, RenderMode},
+};
+#[cfg(feature = "plugin")]
+use super::model::{Animation, AttachmentPoint, Model};
+use super::model::{Cube, Mesh, ModelOptions, Part, TextureAnimation};
 use crate::error::*;
+#[cfg(feature = "plugin")]
 use classicube_sys::Bitmap;
 use log::*;
-use std::{io::Cursor, os::raw::c_int};
+#[cfg(feature = "plugin")]
+use std::os::raw::c_int;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    os::raw::c_float,
+};
 
-#[derive(Debug)]
+/// `ModelVertex` uvs are 15 bits, the top one is a flag
+const UV_POS_MASK: c_float = 0x7FFF as c_float;
+
+#[derive(Debug, Clone)]
 pub struct Blockbench {
     bb: BBModel,
     pixels: Vec<u8>,
+    animation: Option<TextureAnimation>,
 }
 
 impl Blockbench {
     pub fn parse_bbmodel(data: &[u8]) -> Result<Self> {
         let bb: BBModel = serde_json::from_slice(data)?;
+        let pixels = decode_texture(Self::texture(&bb)?, &bb)?;
+
+        Self::check(bb, pixels)
+    }
 
-        ensure!(bb.meta.box_uv, "unimplemented: not using box_uv");
+    /// The model without its texture's source, then the decoded pixels.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut bb = self.bb.clone();
+        for texture in &mut bb.textures {
+            texture.source.clear();
+        }
+
+        let mut out = Vec::with_capacity(self.pixels.len() + 4096);
+        bb.write(&mut out);
+        self.pixels.len().write(&mut out);
+        out.extend_from_slice(&self.pixels);
+
+        out
+    }
+
+    /// From `to_binary`, no json or png involved.
+    pub fn from_binary(mut data: &[u8]) -> Result<Self> {
+        let bb = BBModel::read(&mut data)?;
+        Self::texture(&bb)?;
+
+        let len = usize::read(&mut data)?;
+        ensure!(data.len() == len, "pixels are truncated");
+
+        Self::check(bb, data.to_vec())
+    }
+
+    #[cfg(test)]
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
 
+    fn texture(bb: &BBModel) -> Result<&json::Texture> {
         ensure!(
             bb.textures.len() == 1,
             "unimplemented: textures length {}",
             bb.textures.len()
         );
-        let texture = bb.textures.get(0).chain_err(|| "no texture at index 0")?;
-        // if let json::TextureMode::Bitmap = texture.mode {
-        // } else {
-        //     bail!("not bitmap");
-        // }
+        bb.textures.get(0).chain_err(|| "no texture at index 0")
+    }
 
-        const DATA_URL_START: &str = "data:image/png;base64,";
-        ensure!(
-            texture.source.starts_with(DATA_URL_START),
-            "unimplemented: texture not base64 png data url"
-        );
+    fn check(bb: BBModel, pixels: Vec<u8>) -> Result<Self> {
+        if let Some(problem) = validate::load_problems(&bb, Some(&pixels))
+            .into_iter()
+            .next()
+        {
+            bail!(problem);
+        }
 
-        let base64 = &texture.source[DATA_URL_START.len()..];
-        let data = base64::decode(base64)?;
-        let decoder = png::Decoder::new(Cursor::new(data));
-        let (info, mut reader) = decoder.read_info()?;
-        debug!("{:#?}", info);
-        ensure!(
-            info.width as usize == bb.resolution.width,
-            "texture width mismatch"
-        );
-        ensure!(
-            info.height as usize == bb.resolution.height,
-            "texture height mismatch"
-        );
-        ensure!(
-            info.bit_depth == png::BitDepth::Eight,
-            "unimplemented: texture bitdepth not 8"
-        );
+        let texture = Self::texture(&bb)?;
+        let height = pixels.len() / 4 / bb.resolution.width;
 
-        // Allocate the output buffer.
-        let mut pixels = vec![0; info.buffer_size()];
-        // Read the next frame. Currently this function should only called once.
-        // The default options
-        reader.next_frame(&mut pixels)?;
-        debug!("{} pixels", pixels.len());
-
-        if info.color_type == png::ColorType::RGBA {
-            // cc uses BGRA, so we need to convert based on info.color_type
-            for i in (0..pixels.len()).step_by(4) {
-                // swap blue with red
-                pixels.swap(i, i + 2);
-            }
+        // animated textures are frames stacked on top of each other
+        let frame_height = bb.resolution.height;
+        let frame_count = height / frame_height;
+        let animation = if frame_count > 1 {
+            debug!("{} animation frames", frame_count);
+            Some(TextureAnimation::new(
+                frame_height,
+                texture.frame_time,
+                frame_order(texture, frame_count),
+            ))
         } else {
-            bail!("unimplemented: color_type {:?}", info.color_type);
+            None
+        };
+
+        Ok(Self {
+            pixels,
+            bb,
+            animation,
+        })
+    }
+
+    /// `options` usually start out as `self.options()`.
+    #[cfg(feature = "plugin")]
+    pub fn register_model(mut self, name: &str, options: ModelOptions) {
+        let bmp = Bitmap {
+            Scan0: self.pixels.as_mut_ptr(),
+            Width: self.bb.resolution.width as c_int,
+            Height: (self.pixels.len() / 4 / self.bb.resolution.width) as c_int,
+        };
+
+        let mut attachments = Self::make_attachments(&self.bb);
+        for attachment in &mut attachments {
+            attachment.apply_options(&options);
         }
 
-        for e in &bb.elements {
-            ensure!(e.autouv == 0, "unimplemented: autouv not 0");
-            ensure!(
-                e.name == "cube",
-                "unimplemented: different name {:?}",
-                e.name
-            );
+        let mut animations = Self::make_animations(&self.bb);
+        for animation in &mut animations {
+            animation.apply_options(&options);
         }
 
-        let mut found_non_zero = false;
-        for pixel in &pixels {
-            if *pixel != 0 {
-                found_non_zero = true;
-                break;
-            }
+        let parts = self.parts(&options);
+
+        Model::register(
+            &name,
+            bmp,
+            self.animation,
+            parts,
+            attachments,
+            animations,
+            options,
+        );
+    }
+
+    /// Every cube and mesh, ready to be drawn.
+    pub fn parts(&self, options: &ModelOptions) -> Vec<Part> {
+        let width = self.bb.resolution.width;
+        let frame_height = self.bb.resolution.height;
+
+        let mut parts = Self::make_parts(&self.bb, &options.head_group);
+        for part in &mut parts {
+            part.set_translucent(part.covers_partial_alpha(&self.pixels, width, frame_height));
+            part.apply_options(options);
         }
-        if !found_non_zero {
-            bail!("image is all 0's?");
+
+        parts
+    }
+
+    /// Scale and offset from the file's `blockbench_loader` property.
+    pub fn options(&self) -> ModelOptions {
+        let mut options = ModelOptions::default();
+
+        if let Some(properties) = &self.bb.blockbench_loader {
+            options.scale = properties.scale;
+            options.offset = [
+                properties.offset[0] / 16.0,
+                properties.offset[1] / 16.0,
+                properties.offset[2] / 16.0,
+            ];
         }
 
-        Ok(Self { pixels, bb })
+        options
     }
 
-    pub fn register_model(mut self, name: &str) {
-        let bmp = Bitmap {
-            Scan0: self.pixels.as_mut_ptr(),
-            Width: self.bb.resolution.width as c_int,
-            Height: self.bb.resolution.height as c_int,
-        };
+    /// Locators and null objects, then group pivots so that
+    /// a group named "right_hand" works as well.
+    #[cfg(feature = "plugin")]
+    fn make_attachments(bb: &BBModel) -> Vec<AttachmentPoint> {
+        fn add_groups(outliner: &[Outliner], attachments: &mut Vec<AttachmentPoint>) {
+            for node in outliner {
+                if let Outliner::Group(group) = node {
+                    attachments.push(AttachmentPoint::from_bbmodel_group(group));
+                    add_groups(&group.children, attachments);
+                }
+            }
+        }
+
+        let mut attachments: Vec<AttachmentPoint> = bb
+            .elements
+            .iter()
+            .filter_map(|e| match e {
+                Element::Locator(e) | Element::NullObject(e) => {
+                    Some(AttachmentPoint::from_bbmodel_locator(e))
+                }
+                _ => None,
+            })
+            .collect();
 
-        let mut parts = Vec::new();
+        add_groups(&bb.outliner, &mut attachments);
+
+        attachments
+    }
+
+    #[cfg(feature = "plugin")]
+    fn make_animations(bb: &BBModel) -> Vec<Animation> {
+        let group_origins = group_origins(&bb.outliner);
+
+        bb.animations
+            .iter()
+            .map(|animation| Animation::from_bbmodel(animation, &group_origins))
+            .collect()
+    }
+
+    /// Elements in groups named `head_group` turn with the entity's pitch.
+    fn make_parts(bb: &BBModel, head_group: &str) -> Vec<Part> {
+        let box_uv = bb.meta.box_uv;
+        let texture_size = [
+            bb.resolution.width as c_float,
+            bb.resolution.height as c_float,
+        ];
+        let head_pivots = head_pivots(&bb.outliner, head_group, None);
+        let emissive_texture = matches!(
+            bb.textures.first(),
+            Some(texture) if texture.render_mode == RenderMode::Emissive
+        );
+        let emissive_elements = emissive_elements(&bb.outliner, false);
+        let mut element_groups = element_groups(&bb.outliner, &[]);
+
+        let mut parts = Vec::with_capacity(bb.elements.len());
 
         // east is left
         // top is top
-        for e in self.bb.elements {
-            parts.push(Cube::from_bbmodel_element(e));
+        for e in &bb.elements {
+            let (uuid, name, origin) = match e {
+                Element::Cube(e) => (&e.uuid, &e.name, e.origin),
+                Element::Mesh(e) => (&e.uuid, &e.name, e.origin),
+                _ => continue,
+            };
+
+            let head_pivot = head_pivots.get(uuid).copied().or_else(|| {
+                if name.eq_ignore_ascii_case(head_group) {
+                    Some([origin[0] / 16.0, origin[1] / 16.0, origin[2] / 16.0])
+                } else {
+                    None
+                }
+            });
+            let emissive =
+                emissive_texture || emissive_elements.contains(uuid) || is_emissive_name(name);
+            let groups = element_groups.remove(uuid).unwrap_or_default();
+            let group_names = groups.iter().map(|group| group.name.clone()).collect();
+            let group_uuids = groups.iter().map(|group| group.uuid.clone()).collect();
+
+            let mut part = match e {
+                Element::Cube(e) => {
+                    Part::Cube(Cube::from_bbmodel_element(e.clone(), box_uv, texture_size))
+                }
+                Element::Mesh(e) => Part::Mesh(Mesh::from_bbmodel_element(e.clone())),
+                _ => continue,
+            };
+            part.set_head_pivot(head_pivot);
+            part.set_emissive(emissive);
+            part.set_groups(group_names, group_uuids);
+
+            parts.push(part);
+        }
+
+        parts
+    }
+}
+
+/// The texture's pixels in BGRA like ClassiCube wants.
+fn decode_texture(texture: &json::Texture, bb: &BBModel) -> Result<Vec<u8>> {
+    // if let json::TextureMode::Bitmap = texture.mode {
+    // } else {
+    //     bail!("not bitmap");
+    // }
+
+    const DATA_URL_START: &str = "data:image/png;base64,";
+    ensure!(
+        texture.source.starts_with(DATA_URL_START),
+        "unimplemented: texture not base64 png data url"
+    );
+
+    let base64 = &texture.source[DATA_URL_START.len()..];
+    let data = base64::decode(base64)?;
+    let decoder = png::Decoder::new(Cursor::new(data));
+    let (info, mut reader) = decoder.read_info()?;
+    debug!("{:#?}", info);
+    ensure!(
+        info.width as usize == bb.resolution.width,
+        "texture width mismatch"
+    );
+    ensure!(
+        info.bit_depth == png::BitDepth::Eight,
+        "unimplemented: texture bitdepth not 8"
+    );
+
+    // Allocate the output buffer.
+    let mut pixels = vec![0; info.buffer_size()];
+    // Read the next frame. Currently this function should only called once.
+    // The default options
+    reader.next_frame(&mut pixels)?;
+    debug!("{} pixels", pixels.len());
+
+    if info.color_type == png::ColorType::RGBA {
+        // cc uses BGRA, so we need to convert based on info.color_type
+        for i in (0..pixels.len()).step_by(4) {
+            // swap blue with red
+            pixels.swap(i, i + 2);
+        }
+    } else {
+        bail!("unimplemented: color_type {:?}", info.color_type);
+    }
+
+    Ok(pixels)
+}
+
+/// Which frames an animated texture shows, in order.
+fn frame_order(texture: &json::Texture, frame_count: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = if let FrameOrderType::Custom = texture.frame_order_type {
+        texture
+            .frame_order
+            .split_whitespace()
+            .filter_map(|frame| frame.parse().ok())
+            .filter(|frame| *frame < frame_count)
+            .collect()
+    } else {
+        (0..frame_count).collect()
+    };
+
+    if order.is_empty() {
+        order = (0..frame_count).collect();
+    }
+
+    match texture.frame_order_type {
+        FrameOrderType::Backwards => order.reverse(),
+        FrameOrderType::BackAndForth if order.len() > 2 => {
+            let back: Vec<usize> = order[1..order.len() - 1].iter().rev().copied().collect();
+            order.extend(back);
+        }
+        _ => {}
+    }
+
+    order
+}
+
+/// Names like "lantern_glow" or "eyes_emissive" are drawn unlit.
+fn is_emissive_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with("_emissive") || name.ends_with("_glow")
+}
+
+/// Uuids of elements inside a group with an emissive name.
+fn emissive_elements(outliner: &[Outliner], parent_emissive: bool) -> HashSet<String> {
+    let mut elements = HashSet::new();
+
+    for node in outliner {
+        match node {
+            Outliner::Element(uuid) => {
+                if parent_emissive {
+                    elements.insert(uuid.clone());
+                }
+            }
+
+            Outliner::Group(group) => {
+                let emissive = parent_emissive || is_emissive_name(&group.name);
+                elements.extend(emissive_elements(&group.children, emissive));
+            }
+        }
+    }
+
+    elements
+}
+
+/// The groups each element is in, outermost first, keyed by element uuid.
+fn element_groups<'a>(
+    outliner: &'a [Outliner],
+    parents: &[&'a Group],
+) -> HashMap<String, Vec<&'a Group>> {
+    let mut groups = HashMap::new();
+
+    for node in outliner {
+        match node {
+            Outliner::Element(uuid) => {
+                groups.insert(uuid.clone(), parents.to_vec());
+            }
+
+            Outliner::Group(group) => {
+                let mut parents = parents.to_vec();
+                parents.push(group);
+                groups.extend(element_groups(&group.children, &parents));
+            }
         }
+    }
 
-        Model::register(&name, bmp, parts);
+    groups
+}
+
+/// Every group's pivot point in pixels, keyed by group uuid.
+fn group_origins(outliner: &[Outliner]) -> HashMap<String, [c_float; 3]> {
+    let mut origins = HashMap::new();
+
+    for node in outliner {
+        if let Outliner::Group(group) = node {
+            origins.insert(group.uuid.clone(), group.origin);
+            origins.extend(group_origins(&group.children));
+        }
     }
+
+    origins
+}
+
+/// Elements inside a group named `head_group` get that group's pivot point,
+/// keyed by element uuid.
+fn head_pivots(
+    outliner: &[Outliner],
+    head_group: &str,
+    parent_pivot: Option<[c_float; 3]>,
+) -> HashMap<String, [c_float; 3]> {
+    let mut pivots = HashMap::new();
+
+    for node in outliner {
+        match node {
+            Outliner::Element(uuid) => {
+                if let Some(pivot) = parent_pivot {
+                    pivots.insert(uuid.clone(), pivot);
+                }
+            }
+
+            Outliner::Group(group) => {
+                let pivot = if group.name.eq_ignore_ascii_case(head_group) {
+                    Some([
+                        group.origin[0] / 16.0,
+                        group.origin[1] / 16.0,
+                        group.origin[2] / 16.0,
+                    ])
+                } else {
+                    parent_pivot
+                };
+
+                pivots.extend(head_pivots(&group.children, head_group, pivot));
+            }
+        }
+    }
+
+    pivots
 }
 
 #[test]
@@ -123,3 +457,354 @@ fn test_blockbench() {
 
     drop(Blockbench::parse_bbmodel(&data).unwrap());
 }
+
+#[test]
+fn test_rotated() {
+    let data = std::fs::read("tests/rotated.bbmodel").unwrap();
+    let blockbench = Blockbench::parse_bbmodel(&data).unwrap();
+
+    let cubes: Vec<Cube> = blockbench
+        .bb
+        .elements
+        .into_iter()
+        .filter_map(|e| match e {
+            Element::Cube(e) => Some(Cube::from_bbmodel_element(e, true, [64.0, 64.0])),
+            _ => None,
+        })
+        .collect();
+
+    // only the left arm is rotated, 55 degrees around Y
+    assert_eq!(
+        cubes.iter().filter(|cube| cube.rotation.is_some()).count(),
+        1
+    );
+    let arm = &cubes[2];
+
+    // worked out by hand around the pivot [-3.5, 21.75, 1]
+    for (corner, expected) in &[
+        (arm.from, [-0.446_613_85, 0.671_875, 0.169_992_46]),
+        (arm.to, [-0.134_280_26, 1.421_875, 0.159_795_56]),
+    ] {
+        let actual = arm.transform(*corner);
+        for i in 0..3 {
+            assert!(
+                (actual[i] - expected[i]).abs() < 0.0001,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    // unrotated cubes stay put
+    assert_eq!(cubes[0].transform(cubes[0].from), cubes[0].from);
+}
+
+#[test]
+fn test_mesh() {
+    let data = std::fs::read("tests/mesh.bbmodel").unwrap();
+    let blockbench = Blockbench::parse_bbmodel(&data).unwrap();
+
+    let meshes: Vec<Mesh> = blockbench
+        .bb
+        .elements
+        .into_iter()
+        .filter_map(|e| match e {
+            Element::Mesh(e) => Some(Mesh::from_bbmodel_element(e)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(meshes.len(), 1);
+
+    // a square base and 4 triangle sides
+    let pyramid = &meshes[0];
+    assert_eq!(pyramid.quads.len(), 5);
+    // every quad drawn once, in parts the game can shade
+    let chunks = pyramid.chunks();
+    assert_eq!(
+        chunks
+            .iter()
+            .flatten()
+            .filter(|quad| pyramid.quads.contains(quad))
+            .count(),
+        5
+    );
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 6));
+
+    let close = |a: [f32; 3], b: [f32; 3]| (0..3).all(|i| (a[i] - b[i]).abs() < 0.0001);
+
+    // turned 45 degrees around the pivot at [8, 0, 0], the tip stays above it
+    // and the base's corners end up on the axes
+    let tip = [8.0 / 16.0, 8.0 / 16.0, 0.0];
+    let corner_a = [(8.0 - 32f32.sqrt()) / 16.0, 0.0, 0.0];
+    for quad in &pyramid.quads[1..] {
+        // triangles double up their last vertex
+        assert_eq!(quad[2], quad[3]);
+        assert!(close(quad[2].position, tip));
+    }
+
+    // the base was stored crossing over its diagonal
+    let base = &pyramid.quads[0];
+    assert!(close(base[0].position, corner_a));
+    assert_eq!(base[0].uv, [0.0, 8.0]);
+    assert_eq!(base[1].uv, [8.0, 8.0]);
+    assert_eq!(base[2].uv, [8.0, 16.0]);
+    assert_eq!(base[3].uv, [0.0, 16.0]);
+}
+
+#[test]
+fn test_names() {
+    let data = std::fs::read("tests/Player.bbmodel").unwrap();
+    let blockbench = Blockbench::parse_bbmodel(&data).unwrap();
+    let parts = Blockbench::make_parts(&blockbench.bb, "head");
+
+    assert_eq!(parts.len(), 12);
+    assert!(parts.iter().all(|part| part.name() == "cube"));
+
+    // the 2 cubes in the "Head" group turn around its pivot
+    let heads: Vec<&Part> = parts.iter().filter(|part| part.is_head()).collect();
+    assert_eq!(heads.len(), 2);
+    for part in heads {
+        if let Part::Cube(cube) = part {
+            assert_eq!(cube.head_pivot, Some([3.0 / 16.0, 12.0 / 16.0, 3.0 / 16.0]));
+        }
+    }
+
+    // renamed elements are fine too
+    let renamed =
+        String::from_utf8(data)
+            .unwrap()
+            .replacen("\"name\":\"cube\"", "\"name\":\"left_arm\"", 1);
+    let blockbench = Blockbench::parse_bbmodel(renamed.as_bytes()).unwrap();
+    let parts = Blockbench::make_parts(&blockbench.bb, "head");
+    assert_eq!(parts[0].name(), "left_arm");
+}
+
+#[test]
+fn test_box_uv_matches_file() {
+    // blockbench saves the uvs it worked out for box uv cubes,
+    // we should come up with the same ones
+    for path in &[
+        "tests/Player.bbmodel",
+        "tests/rotated.bbmodel",
+        "tests/mesh.bbmodel",
+    ] {
+        let data = std::fs::read(path).unwrap();
+        let bb: BBModel = serde_json::from_slice(&data).unwrap();
+        assert!(bb.meta.box_uv);
+
+        let texture_size = [
+            bb.resolution.width as c_float,
+            bb.resolution.height as c_float,
+        ];
+
+        for e in bb.elements {
+            if let Element::Cube(e) = e {
+                let json::Faces {
+                    north,
+                    east,
+                    south,
+                    west,
+                    up,
+                    down,
+                } = &e.faces;
+                let expected = [up.uv, down.uv, north.uv, south.uv, east.uv, west.uv];
+
+                let cube = Cube::from_bbmodel_element(e, true, texture_size);
+                for (face, expected) in cube.faces.iter().zip(&expected) {
+                    assert_eq!(face.uv, *expected, "{} {:?}", path, cube.from);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "plugin")]
+fn test_attachments() {
+    use classicube_sys::{Entity, Vec3};
+
+    let data = std::fs::read("tests/mesh.bbmodel").unwrap();
+    let blockbench = Blockbench::parse_bbmodel(&data).unwrap();
+    let attachments = Blockbench::make_attachments(&blockbench.bb);
+
+    let names: Vec<&str> = attachments.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(names, vec!["nametag", "hand"]);
+    assert!(attachments[1].is_hand());
+    assert_eq!(attachments[0].position, [0.0, 20.0 / 16.0, 0.0]);
+
+    // a player at [10, 64, 10], turned a quarter to the right, twice as big
+    let mut entity: Entity = unsafe { std::mem::zeroed() };
+    entity.Position = Vec3 {
+        X: 10.0,
+        Y: 64.0,
+        Z: 10.0,
+    };
+    entity.RotY = 90.0;
+    entity.ModelScale = Vec3 {
+        X: 2.0,
+        Y: 2.0,
+        Z: 2.0,
+    };
+
+    // the hand at [-6, 12, 2] pixels goes from the entity's left/front to its right/back
+    let transform = attachments[1].transform(&entity);
+    let expected = [10.0 - 4.0 / 16.0, 64.0 + 24.0 / 16.0, 10.0 - 12.0 / 16.0];
+    for i in 0..3 {
+        assert!(
+            (transform.position[i] - expected[i]).abs() < 0.0001,
+            "{:?} != {:?}",
+            transform.position,
+            expected
+        );
+    }
+
+    let matrix = transform.to_matrix();
+    assert!((matrix.row4.X - expected[0]).abs() < 0.0001);
+    assert!((matrix.row4.Y - expected[1]).abs() < 0.0001);
+    assert!((matrix.row4.Z - expected[2]).abs() < 0.0001);
+    assert_eq!(matrix.row4.W, 1.0);
+}
+
+#[test]
+#[cfg(feature = "plugin")]
+fn test_group_attachments() {
+    let data = std::fs::read("tests/Player.bbmodel").unwrap();
+    let blockbench = Blockbench::parse_bbmodel(&data).unwrap();
+    let attachments = Blockbench::make_attachments(&blockbench.bb);
+
+    let names: Vec<&str> = attachments.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "Head",
+            "Right_Arm",
+            "Left_Arm",
+            "Torso",
+            "Right_Leg",
+            "Left_Leg"
+        ]
+    );
+    assert_eq!(
+        attachments[2].position,
+        [-5.0 / 16.0, 19.75 / 16.0, 1.0 / 16.0]
+    );
+
+    // "Right_Arm" is the arm, not the hand
+    assert!(attachments.iter().all(|a| !a.is_hand()));
+}
+
+#[test]
+fn test_animated_texture() {
+    let data = std::fs::read("tests/animated.bbmodel").unwrap();
+    let blockbench = Blockbench::parse_bbmodel(&data).unwrap();
+
+    // 3 frames of 16x16 stacked into 16x48
+    assert_eq!(blockbench.pixels.len(), 16 * 48 * 4);
+    let animation = blockbench.animation.unwrap();
+    assert_eq!(animation.frame_height, 16);
+    assert_eq!(animation.frame_time, 2);
+    assert_eq!(animation.order, vec![0, 1, 2, 1]);
+
+    // BGRA, red then green then blue
+    let frame_bytes = 16 * 16 * 4;
+    assert_eq!(&blockbench.pixels[..4], &[0, 0, 255, 255]);
+    assert_eq!(&blockbench.pixels[frame_bytes..][..4], &[0, 255, 0, 255]);
+    assert_eq!(
+        &blockbench.pixels[frame_bytes * 2..][..4],
+        &[255, 0, 0, 255]
+    );
+}
+
+#[test]
+fn test_frame_order() {
+    let data = std::fs::read("tests/animated.bbmodel").unwrap();
+    let mut bb: BBModel = serde_json::from_slice(&data).unwrap();
+    let texture = &mut bb.textures[0];
+
+    texture.frame_order_type = FrameOrderType::Loop;
+    assert_eq!(frame_order(texture, 3), vec![0, 1, 2]);
+
+    texture.frame_order_type = FrameOrderType::Backwards;
+    assert_eq!(frame_order(texture, 3), vec![2, 1, 0]);
+
+    texture.frame_order_type = FrameOrderType::Custom;
+    texture.frame_order = "0 0 2 7 1".to_string();
+    assert_eq!(frame_order(texture, 3), vec![0, 0, 2, 1]);
+
+    // nothing usable falls back to every frame
+    texture.frame_order = "".to_string();
+    assert_eq!(frame_order(texture, 3), vec![0, 1, 2]);
+
+    // static textures don't animate
+    let data = std::fs::read("tests/mesh.bbmodel").unwrap();
+    assert!(Blockbench::parse_bbmodel(&data)
+        .unwrap()
+        .animation
+        .is_none());
+}
+
+#[test]
+fn test_emissive() {
+    fn emissive_parts(bb: BBModel) -> Vec<bool> {
+        Blockbench::make_parts(&bb, "head")
+            .iter()
+            .map(|part| part.is_emissive())
+            .collect()
+    }
+
+    let data = std::fs::read("tests/mesh.bbmodel").unwrap();
+    let parse = || Blockbench::parse_bbmodel(&data).unwrap().bb;
+
+    assert_eq!(emissive_parts(parse()), vec![false, false]);
+
+    // by element name
+    let mut bb = parse();
+    if let Element::Mesh(mesh) = &mut bb.elements[1] {
+        mesh.name = "mesh_Glow".to_string();
+    }
+    assert_eq!(emissive_parts(bb), vec![false, true]);
+
+    // by group name
+    let mut bb = parse();
+    let cube = bb.outliner.remove(0);
+    bb.outliner.push(Outliner::Group(json::Group {
+        name: "lantern_emissive".to_string(),
+        origin: [0.0, 0.0, 0.0],
+        children: vec![cube],
+        uuid: "lantern".to_string(),
+    }));
+    assert_eq!(emissive_parts(bb), vec![true, false]);
+
+    // by texture render mode
+    let mut bb = parse();
+    bb.textures[0].render_mode = RenderMode::Emissive;
+    assert_eq!(emissive_parts(bb), vec![true, true]);
+}
+
+#[test]
+fn test_scale_and_offset() {
+    let data = std::fs::read("tests/mesh.bbmodel").unwrap();
+    let mut value: serde_json::Value = serde_json::from_slice(&data).unwrap();
+    value["blockbench_loader"] = serde_json::json!({ "scale": 2.0, "offset": [0, 16, 0] });
+    let data = serde_json::to_vec(&value).unwrap();
+
+    let blockbench = Blockbench::parse_bbmodel(&data).unwrap();
+    let options = blockbench.options();
+    assert_eq!(options.scale, 2.0);
+    assert_eq!(options.offset, [0.0, 1.0, 0.0]);
+
+    let mut parts = Blockbench::make_parts(&blockbench.bb, "head");
+    parts[0].apply_options(&options);
+    if let Part::Cube(cube) = &parts[0] {
+        // [-2, 0, -2] to [2, 4, 2] pixels, twice as big and a block up
+        assert_eq!(cube.from, [-0.25, 1.0, -0.25]);
+        assert_eq!(cube.to, [0.25, 1.5, 0.25]);
+    } else {
+        panic!("not a cube");
+    }
+
+    value["blockbench_loader"] = serde_json::json!({ "scale": 0.0 });
+    let data = serde_json::to_vec(&value).unwrap();
+    assert!(Blockbench::parse_bbmodel(&data).is_err());
+}
@@ -0,0 +1,560 @@
+//! Everything wrong with a .bbmodel file at once, for artists to check their
+//! files before giving them to the game.
+
+use super::{
+    decode_texture,
+    json::{self, BBModel, Element, ModelFormat, Outliner},
+    Blockbench, UV_POS_MASK,
+};
+use crate::plugin::model::{Mesh, Part, CUBE_VERTICES, MAX_VERTICES, UV_PRECISION};
+use std::{fmt, os::raw::c_float};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// the game won't load it, or it won't look right
+    Error,
+    /// probably a mistake
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Problem {
+    fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub name: String,
+    pub resolution: [usize; 2],
+    pub groups: usize,
+    pub cubes: usize,
+    pub meshes: usize,
+    /// locators and null objects
+    pub locators: usize,
+    pub vertices: usize,
+    /// name, width and height
+    pub textures: Vec<(String, u32, u32)>,
+    /// name, length in seconds and how many groups move
+    pub animations: Vec<(String, c_float, usize)>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "model {:?}", self.name)?;
+        writeln!(
+            f,
+            "  {} groups, {} cubes, {} meshes, {} locators",
+            self.groups, self.cubes, self.meshes, self.locators
+        )?;
+        writeln!(f, "  {} of {} vertices", self.vertices, MAX_VERTICES)?;
+
+        writeln!(
+            f,
+            "  {} textures at {}x{}",
+            self.textures.len(),
+            self.resolution[0],
+            self.resolution[1]
+        )?;
+        for (name, width, height) in &self.textures {
+            writeln!(f, "    {:?} {}x{}", name, width, height)?;
+        }
+
+        writeln!(f, "  {} animations", self.animations.len())?;
+        for (name, length, groups) in &self.animations {
+            writeln!(f, "    {:?} {}s, {} groups", name, length, groups)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks `data` as a .bbmodel file, returning every problem found
+/// and a summary if it could be read at all.
+pub fn check(data: &[u8]) -> (Vec<Problem>, Option<Summary>) {
+    let bb: BBModel = match serde_json::from_slice(data) {
+        Ok(bb) => bb,
+        Err(e) => return (vec![Problem::error(format!("not a .bbmodel: {}", e))], None),
+    };
+
+    let mut problems = Vec::new();
+    let mut textures = Vec::new();
+    let mut first_pixels = None;
+
+    for (i, texture) in bb.textures.iter().enumerate() {
+        match decode_texture(texture, &bb) {
+            Ok(pixels) => {
+                let width = bb.resolution.width as u32;
+                let height = (pixels.len() / 4 / bb.resolution.width.max(1)) as u32;
+                textures.push((texture.name.clone(), width, height));
+
+                if i == 0 {
+                    first_pixels = Some(pixels);
+                }
+            }
+            Err(e) => problems.push(Problem::error(format!("texture {:?}: {}", texture.name, e))),
+        }
+    }
+
+    problems.extend(
+        load_problems(&bb, first_pixels.as_deref())
+            .into_iter()
+            .map(Problem::error),
+    );
+    // uvs are laid out on the one texture, otherwise that's already an error
+    if bb.textures.len() == 1 {
+        problems.extend(uv_problems(&bb));
+    }
+    problems.extend(mesh_problems(&bb));
+    problems.extend(animation_problems(&bb));
+
+    // whatever the game would still say no to
+    let has_errors = problems
+        .iter()
+        .any(|problem| problem.severity == Severity::Error);
+    if !has_errors {
+        if let Err(e) = Blockbench::parse_bbmodel(data) {
+            problems.push(Problem::error(e.to_string()));
+        }
+    }
+
+    let summary = summarize(&bb, textures);
+    (problems, Some(summary))
+}
+
+/// Everything that stops `Blockbench::parse_bbmodel`, not only the first thing.
+///
+/// `pixels` of the first texture if it could be decoded.
+pub fn load_problems(bb: &BBModel, pixels: Option<&[u8]>) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if bb.textures.len() != 1 {
+        problems.push(format!(
+            "unimplemented: textures length {}",
+            bb.textures.len()
+        ));
+    }
+
+    let width = bb.resolution.width;
+    let frame_height = bb.resolution.height;
+
+    if let Some(pixels) = pixels {
+        let height = pixels.len() / 4 / width.max(1);
+        // animated textures are frames stacked on top of each other
+        let frame_count = height / frame_height.max(1);
+
+        if width == 0 || width * height * 4 != pixels.len() {
+            problems.push("texture width mismatch".to_string());
+        } else if frame_count == 0 || frame_count * frame_height != height {
+            problems.push("texture height mismatch".to_string());
+        }
+
+        if pixels.iter().all(|pixel| *pixel == 0) {
+            problems.push("image is all 0's?".to_string());
+        }
+    }
+
+    if width as c_float * UV_PRECISION > UV_POS_MASK
+        || frame_height as c_float * UV_PRECISION > UV_POS_MASK
+    {
+        problems.push(format!(
+            "unimplemented: texture larger than {}",
+            UV_POS_MASK / UV_PRECISION
+        ));
+    }
+
+    let mut vertex_count = 0;
+    for e in &bb.elements {
+        match e {
+            Element::Cube(_) => {
+                vertex_count += CUBE_VERTICES;
+            }
+
+            Element::Mesh(e) => {
+                for face in e.faces.values() {
+                    for key in &face.vertices {
+                        if !e.vertices.contains_key(key) {
+                            problems.push(format!(
+                                "mesh {:?} face uses missing vertex {:?}",
+                                e.name, key
+                            ));
+                        }
+                        if !face.uv.contains_key(key) {
+                            problems.push(format!(
+                                "mesh {:?} face has no uv for vertex {:?}",
+                                e.name, key
+                            ));
+                        }
+                    }
+                }
+                vertex_count += mesh_vertex_count(e);
+            }
+
+            // just points, nothing to draw
+            Element::Locator(_) | Element::NullObject(_) => {}
+
+            Element::Unknown(kind) => {
+                problems.push(format!("unimplemented: element type {:?}", kind));
+            }
+        }
+    }
+    if vertex_count > MAX_VERTICES {
+        problems.push(format!(
+            "too many vertices ({} > {}), a cube takes {}",
+            vertex_count, MAX_VERTICES, CUBE_VERTICES
+        ));
+    }
+
+    if let ModelFormat::JavaBlock = bb.meta.model_format {
+        for e in &bb.elements {
+            if let Element::Cube(json::Cube {
+                rotation: Some(rotation),
+                ..
+            }) = e
+            {
+                let axes = rotation.iter().filter(|angle| **angle != 0.0).count();
+                if axes > 1 {
+                    problems.push(format!("java block element rotated on {} axes", axes));
+                }
+
+                for angle in rotation.iter() {
+                    if angle.abs() > 45.0 || (angle / 22.5).fract() != 0.0 {
+                        problems.push(format!(
+                            "java block element rotation {} not a multiple of 22.5 up to 45",
+                            angle
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(properties) = &bb.blockbench_loader {
+        if properties.scale <= 0.0 {
+            problems.push(format!("scale {} must be above 0", properties.scale));
+        }
+    }
+
+    problems
+}
+
+/// `[u1, v1, u2, v2]` around each quad with an area, by part.
+fn uv_rects(parts: &[Part]) -> Vec<Vec<[c_float; 4]>> {
+    parts
+        .iter()
+        .map(|part| {
+            part.quads()
+                .iter()
+                .map(|quad| {
+                    let mut rect = [
+                        c_float::INFINITY,
+                        c_float::INFINITY,
+                        c_float::NEG_INFINITY,
+                        c_float::NEG_INFINITY,
+                    ];
+                    for vertex in quad {
+                        rect[0] = rect[0].min(vertex.uv[0]);
+                        rect[1] = rect[1].min(vertex.uv[1]);
+                        rect[2] = rect[2].max(vertex.uv[0]);
+                        rect[3] = rect[3].max(vertex.uv[1]);
+                    }
+                    rect
+                })
+                .filter(|rect| rect[2] > rect[0] && rect[3] > rect[1])
+                .collect()
+        })
+        .collect()
+}
+
+fn overlaps(a: &[c_float; 4], b: &[c_float; 4]) -> bool {
+    a[0] < b[2] && b[0] < a[2] && a[1] < b[3] && b[1] < a[3]
+}
+
+/// Uvs off the texture are errors, elements sharing texture space are warnings
+/// since mirrored limbs often do that on purpose.
+fn uv_problems(bb: &BBModel) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    // one part per cube and mesh, broken ones are already errors
+    let drawn = bb
+        .elements
+        .iter()
+        .filter(|e| matches!(e, Element::Cube(_) | Element::Mesh(_)));
+    let parts: Vec<Part> = Blockbench::make_parts(bb, "head")
+        .into_iter()
+        .zip(drawn)
+        .filter(|(_, e)| match e {
+            Element::Mesh(e) => e.faces.values().all(|face| {
+                face.vertices
+                    .iter()
+                    .all(|key| e.vertices.contains_key(key) && face.uv.contains_key(key))
+            }),
+            _ => true,
+        })
+        .map(|(part, _)| part)
+        .collect();
+
+    let rects = uv_rects(&parts);
+    let width = bb.resolution.width as c_float;
+    let height = bb.resolution.height as c_float;
+
+    for (part, rects) in parts.iter().zip(&rects) {
+        let outside = rects
+            .iter()
+            .any(|rect| rect[0] < 0.0 || rect[1] < 0.0 || rect[2] > width || rect[3] > height);
+        if outside {
+            problems.push(Problem::error(format!(
+                "uvs of {:?} go outside the {}x{} texture",
+                part.name(),
+                width,
+                height
+            )));
+        }
+    }
+
+    for (i, (a, a_rects)) in parts.iter().zip(&rects).enumerate() {
+        for (b, b_rects) in parts.iter().zip(&rects).skip(i + 1) {
+            let overlap = a_rects
+                .iter()
+                .any(|a_rect| b_rects.iter().any(|b_rect| overlaps(a_rect, b_rect)));
+            if overlap {
+                problems.push(Problem::warning(format!(
+                    "uvs of {:?} and {:?} overlap",
+                    a.name(),
+                    b.name()
+                )));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Faces `Mesh::from_bbmodel_element` has to cut up, which only looks
+/// right if they're flat and convex.
+fn mesh_problems(bb: &BBModel) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for e in &bb.elements {
+        if let Element::Mesh(e) = e {
+            for face in e.faces.values() {
+                if face.vertices.len() > 4 {
+                    problems.push(Problem::warning(format!(
+                        "mesh {:?} face with {} vertices is cut into {} triangles",
+                        e.name,
+                        face.vertices.len(),
+                        face.vertices.len() - 2
+                    )));
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+/// What `Animation::from_bbmodel` would skip or guess at.
+fn animation_problems(bb: &BBModel) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for animation in &bb.animations {
+        for animator in animation.animators.values() {
+            for keyframe in &animator.keyframes {
+                if !["rotation", "position", "scale"].contains(&keyframe.channel.as_str()) {
+                    continue;
+                }
+
+                for point in &keyframe.data_points {
+                    for molang in &[&point.x, &point.y, &point.z] {
+                        if molang.value().is_none() {
+                            problems.push(Problem::warning(format!(
+                                "animation {:?}: unsupported molang {:?} on {:?}, using 0",
+                                animation.name, molang, animator.name
+                            )));
+                        }
+                    }
+                }
+
+                if keyframe.interpolation == "catmullrom" || keyframe.interpolation == "bezier" {
+                    problems.push(Problem::warning(format!(
+                        "animation {:?}: {} interpolation on {:?} is played as linear",
+                        animation.name, keyframe.interpolation, animator.name
+                    )));
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+/// What the mesh takes once split into `ModelPart`s, fillers included.
+fn mesh_vertex_count(e: &json::Mesh) -> usize {
+    Mesh::from_bbmodel_element(e.clone())
+        .chunks()
+        .iter()
+        .map(|chunk| chunk.len() * 4)
+        .sum()
+}
+
+fn summarize(bb: &BBModel, textures: Vec<(String, u32, u32)>) -> Summary {
+    fn count_groups(outliner: &[Outliner]) -> usize {
+        outliner
+            .iter()
+            .map(|node| match node {
+                Outliner::Group(group) => 1 + count_groups(&group.children),
+                Outliner::Element(_) => 0,
+            })
+            .sum()
+    }
+
+    let count = |f: fn(&Element) -> bool| bb.elements.iter().filter(|e| f(e)).count();
+
+    let mut vertices = 0;
+    for e in &bb.elements {
+        match e {
+            Element::Cube(_) => vertices += CUBE_VERTICES,
+            Element::Mesh(e) => vertices += mesh_vertex_count(e),
+            _ => {}
+        }
+    }
+
+    Summary {
+        name: bb.name.clone(),
+        resolution: [bb.resolution.width, bb.resolution.height],
+        groups: count_groups(&bb.outliner),
+        cubes: count(|e| matches!(e, Element::Cube(_))),
+        meshes: count(|e| matches!(e, Element::Mesh(_))),
+        locators: count(|e| matches!(e, Element::Locator(_) | Element::NullObject(_))),
+        vertices,
+        textures,
+        animations: bb
+            .animations
+            .iter()
+            .map(|animation| {
+                (
+                    animation.name.clone(),
+                    animation.length,
+                    animation.animators.len(),
+                )
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_check() {
+    let data = std::fs::read("tests/Player.bbmodel").unwrap();
+    let (problems, summary) = check(&data);
+    assert!(
+        problems
+            .iter()
+            .all(|problem| problem.severity == Severity::Warning),
+        "{:?}",
+        problems
+    );
+
+    let summary = summary.unwrap();
+    assert_eq!(summary.cubes, 12);
+    assert_eq!(summary.vertices, 12 * CUBE_VERTICES);
+    assert_eq!(summary.textures.len(), 1);
+    assert_eq!(summary.resolution, [64, 64]);
+
+    let errors = |bb: &BBModel| -> Vec<String> {
+        let (problems, _) = check(&serde_json::to_vec(bb).unwrap());
+        problems
+            .into_iter()
+            .filter(|problem| problem.severity == Severity::Error)
+            .map(|problem| problem.message)
+            .collect()
+    };
+
+    // several things wrong at once
+    let mut bb: BBModel = serde_json::from_slice(&data).unwrap();
+    bb.blockbench_loader = Some(json::LoaderProperties {
+        scale: 0.0,
+        offset: [0.0; 3],
+    });
+    if let Element::Cube(cube) = &mut bb.elements[0] {
+        cube.uv_offset = Some([62.0, 62.0]);
+    }
+    let found = errors(&bb);
+    assert_eq!(found.len(), 2, "{:?}", found);
+    assert!(found[0].contains("scale"));
+    assert!(found[1].contains("outside"));
+
+    // uvs aren't checked without exactly one texture to put them on
+    bb.textures.push(bb.textures[0].clone());
+    let found = errors(&bb);
+    assert_eq!(found.len(), 2, "{:?}", found);
+    assert!(found[0].contains("textures length 2"));
+    bb.textures.clear();
+    let found = errors(&bb);
+    assert_eq!(found.len(), 2, "{:?}", found);
+    assert!(found[0].contains("textures length 0"));
+
+    let (problems, summary) = check(b"{}");
+    assert_eq!(problems.len(), 1);
+    assert!(summary.is_none());
+}
+
+#[test]
+fn test_overlaps() {
+    assert!(overlaps(&[0.0, 0.0, 4.0, 4.0], &[2.0, 2.0, 6.0, 6.0]));
+    // touching edges is fine
+    assert!(!overlaps(&[0.0, 0.0, 4.0, 4.0], &[4.0, 0.0, 8.0, 4.0]));
+}
+
+#[test]
+fn test_mesh_problems() {
+    let data = std::fs::read("tests/mesh.bbmodel").unwrap();
+    let mut bb: serde_json::Value = serde_json::from_slice(&data).unwrap();
+    let mesh = bb["elements"]
+        .as_array_mut()
+        .unwrap()
+        .iter_mut()
+        .find(|e| e["type"] == "mesh")
+        .unwrap();
+    mesh["faces"]["ngon"] = serde_json::json!({
+        "vertices": ["a", "b", "c", "d", "e"],
+        "uv": { "a": [0, 8], "b": [8, 8], "c": [8, 16], "d": [0, 16], "e": [4, 12] },
+        "texture": 0
+    });
+
+    let (problems, _) = check(&serde_json::to_vec(&bb).unwrap());
+    assert!(
+        problems
+            .iter()
+            .all(|problem| problem.severity == Severity::Warning),
+        "{:?}",
+        problems
+    );
+    assert!(problems
+        .iter()
+        .any(|problem| problem.message.contains("cut into 3 triangles")));
+}
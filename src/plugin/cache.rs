@@ -0,0 +1,79 @@
+//! Model files from servers, stored by their sha256 so they're only sent once.
+
+use crate::error::*;
+use sha2::{Digest, Sha256};
+use std::{fs, path::PathBuf};
+
+pub const CACHE_PATH: &str = "plugins/blockbench-cache";
+
+pub type Hash = [u8; 32];
+
+pub fn hash(data: &[u8]) -> Hash {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&Sha256::digest(data));
+    hash
+}
+
+pub fn to_hex(hash: &Hash) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(CACHE_PATH)
+    }
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn path(&self, hash: &Hash) -> PathBuf {
+        self.dir.join(format!("{}.bbmodel", to_hex(hash)))
+    }
+
+    /// The file's data unless it's missing or was changed since.
+    pub fn get(&self, hash: &Hash) -> Option<Vec<u8>> {
+        let data = fs::read(self.path(hash)).ok()?;
+
+        if self::hash(&data) == *hash {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    pub fn put(&self, data: &[u8]) -> Result<Hash> {
+        let hash = hash(data);
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(&hash), data)?;
+
+        Ok(hash)
+    }
+}
+
+#[test]
+fn test_cache() {
+    let dir = std::env::temp_dir().join("blockbench-loader-test-cache");
+    let _ = fs::remove_dir_all(&dir);
+    let cache = Cache::new(&dir);
+
+    let hash = cache.put(b"model").unwrap();
+    assert_eq!(
+        cache.path(&hash),
+        dir.join("9372c470eeadd5ecd9c3c74c2b3cb633f8e2f2fad799250a0f70d652b6b825e4.bbmodel")
+    );
+    assert_eq!(cache.get(&hash).unwrap(), b"model");
+    assert_eq!(cache.get(&self::hash(b"other")), None);
+
+    fs::write(cache.path(&hash), "tampered").unwrap();
+    assert_eq!(cache.get(&hash), None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
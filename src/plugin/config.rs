@@ -0,0 +1,140 @@
+//! `plugins/blockbench.toml`, written with the defaults if it doesn't exist.
+
+use crate::error::*;
+use log::LevelFilter;
+use serde::{de, Deserialize, Deserializer};
+use std::{cell::RefCell, fs, path::PathBuf, time::Duration};
+
+pub const CONFIG_PATH: &str = "plugins/blockbench.toml";
+
+const DEFAULT_CONFIG: &str = r#"# directories to load .bbmodel files (and .zip files of them) from, earlier ones win when names clash,
+# subfolders are part of the name so "mobs/zombie.bbmodel" is the model "mobs/zombie"
+model_paths = ["plugins/blockbench"]
+
+# off, error, warn, info, debug or trace
+log_level = "debug"
+
+# also log to this file
+# log_file = "plugins/blockbench.log"
+
+# reload models when their files change
+watch = true
+
+# wait this long after a change before reloading
+debounce_ms = 1000
+
+# how to show models that fail to load: "chat", "log" or "panic"
+error_display = "chat"
+
+# keep parsed models in plugins/blockbench-cache for faster startup
+disk_cache = true
+"#;
+
+thread_local!(
+    static CONFIG: RefCell<Config> = RefCell::new(Config::default());
+);
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub model_paths: Vec<PathBuf>,
+    #[serde(deserialize_with = "deserialize_log_level")]
+    pub log_level: LevelFilter,
+    pub log_file: Option<PathBuf>,
+    pub watch: bool,
+    pub debounce_ms: u64,
+    pub error_display: ErrorDisplay,
+    pub disk_cache: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorDisplay {
+    /// log and print in chat
+    Chat,
+    /// only log
+    Log,
+    /// crash the game
+    Panic,
+}
+
+/// "off", "error", "warn", "info", "debug" or "trace", any case.
+fn deserialize_log_level<'de, D>(deserializer: D) -> std::result::Result<LevelFilter, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let level = String::deserialize(deserializer)?;
+    level
+        .parse()
+        .map_err(|_| de::Error::custom(format!("unknown log_level {:?}", level)))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            model_paths: vec![PathBuf::from("plugins/blockbench")],
+            log_level: LevelFilter::Debug,
+            log_file: None,
+            watch: true,
+            debounce_ms: 1000,
+            error_display: ErrorDisplay::Chat,
+            disk_cache: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(data: &str) -> Result<Self> {
+        Ok(toml::from_str(data)?)
+    }
+
+    /// Reads `CONFIG_PATH`, creating it first if needed.
+    pub fn load() -> Result<Self> {
+        let path = PathBuf::from(CONFIG_PATH);
+        if !path.is_file() {
+            fs::write(&path, DEFAULT_CONFIG)?;
+        }
+
+        Self::parse(&fs::read_to_string(&path)?)
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+}
+
+pub fn set(config: Config) {
+    CONFIG.with(|cell| *cell.borrow_mut() = config);
+}
+
+pub fn get() -> Config {
+    CONFIG.with(|cell| cell.borrow().clone())
+}
+
+#[test]
+fn test_default_config() {
+    assert_eq!(Config::parse(DEFAULT_CONFIG).unwrap(), Config::default());
+}
+
+#[test]
+fn test_config() {
+    let config = Config::parse(
+        "model_paths = [\"models\", \"more models\"]\nlog_level = \"warn\"\nwatch = false\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.model_paths,
+        vec![PathBuf::from("models"), PathBuf::from("more models")]
+    );
+    assert_eq!(config.log_level, LevelFilter::Warn);
+    assert!(!config.watch);
+
+    // unset keys keep their defaults
+    assert_eq!(config.debounce(), Duration::from_secs(1));
+    assert_eq!(config.error_display, ErrorDisplay::Chat);
+
+    assert!(Config::parse("error_display = \"popup\"").is_err());
+    assert!(Config::parse("log_level = \"verbose\"").is_err());
+    assert!(Config::parse("wacth = false").is_err());
+}
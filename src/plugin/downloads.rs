@@ -0,0 +1,191 @@
+//! Models the server announces by URL, fetched off the game thread,
+//! checked against their sha256 and cached.
+
+use super::{
+    cache::{self, Cache, Hash},
+    loader::report_error,
+    server,
+};
+use crate::error::*;
+use log::*;
+use std::{
+    cell::RefCell,
+    io::Read,
+    path::Path,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+thread_local!(
+    static FINISHED: RefCell<Option<(Sender<Finished>, Receiver<Finished>)>> = Default::default();
+);
+
+type Finished = (Download, Result<Vec<u8>>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Download {
+    pub name: String,
+    pub url: String,
+    pub hash: Hash,
+}
+
+impl Download {
+    /// One `name url sha256` per line.
+    pub fn parse_list(text: &str) -> Result<Vec<Self>> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                ensure!(parts.len() == 3, "expected \"name url sha256\": {:?}", line);
+
+                Ok(Self {
+                    name: parts[0].to_string(),
+                    url: parts[1].to_string(),
+                    hash: parse_hash(parts[2])?,
+                })
+            })
+            .collect()
+    }
+
+    /// From the cache if we have it, otherwise from `url`.
+    pub fn fetch(&self, cache: &Cache) -> Result<Vec<u8>> {
+        if let Some(data) = cache.get(&self.hash) {
+            return Ok(data);
+        }
+
+        debug!("downloading {:?} from {}", self.name, self.url);
+        let data = get(&self.url)?;
+
+        let hash = cache::hash(&data);
+        ensure!(
+            hash == self.hash,
+            "{} has sha256 {}, expected {}",
+            self.url,
+            cache::to_hex(&hash),
+            cache::to_hex(&self.hash)
+        );
+
+        cache.put(&data)?;
+        Ok(data)
+    }
+}
+
+fn parse_hash(hex: &str) -> Result<Hash> {
+    ensure!(hex.len() == 64 && hex.is_ascii(), "bad sha256 {:?}", hex);
+
+    let mut hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+
+    Ok(hash)
+}
+
+fn get(url: &str) -> Result<Vec<u8>> {
+    let response = ureq::get(url).timeout(TIMEOUT).call();
+    if let Some(e) = response.synthetic_error() {
+        bail!("{}: {}", url, e);
+    }
+    ensure!(response.ok(), "{}: status {}", url, response.status());
+
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(server::MAX_LENGTH as u64 + 1)
+        .read_to_end(&mut data)?;
+    ensure!(data.len() <= server::MAX_LENGTH, "{} is too big", url);
+
+    Ok(data)
+}
+
+/// Fetches in the background, `tick` registers them when they're done.
+pub fn start(downloads: Vec<Download>) {
+    let sender = FINISHED.with(|cell| {
+        let finished = &mut *cell.borrow_mut();
+        finished.get_or_insert_with(channel).0.clone()
+    });
+
+    thread::spawn(move || {
+        let cache = Cache::default();
+        for download in downloads {
+            let result = download.fetch(&cache);
+            if sender.send((download, result)).is_err() {
+                // freed while we were downloading
+                break;
+            }
+        }
+    });
+}
+
+pub fn tick() {
+    let finished: Vec<Finished> = FINISHED.with(|cell| match &*cell.borrow() {
+        Some((_, receiver)) => receiver.try_iter().collect(),
+        None => Vec::new(),
+    });
+
+    for (download, result) in finished {
+        match result {
+            Ok(data) => server::load(&download.name, &download.hash, &data),
+            Err(e) => report_error(Path::new(&download.url), &e),
+        }
+    }
+}
+
+pub fn free() {
+    FINISHED.with(|cell| drop(cell.borrow_mut().take()));
+}
+
+#[test]
+fn test_download() {
+    use std::{fs, io::Write, net::TcpListener};
+
+    let data = fs::read("tests/animated.bbmodel").unwrap();
+    let hash = cache::hash(&data);
+
+    // answers every request with the model
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/animated.bbmodel", listener.local_addr().unwrap());
+    let served = data.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                served.len()
+            )
+            .unwrap();
+            stream.write_all(&served).unwrap();
+        }
+    });
+
+    let list = format!(
+        "\nmobs/animated {} {}\n\nbad {} {}\n",
+        url,
+        cache::to_hex(&hash),
+        url,
+        cache::to_hex(&cache::hash(b"something else"))
+    );
+    let downloads = Download::parse_list(&list).unwrap();
+    assert_eq!(downloads.len(), 2);
+    assert_eq!(downloads[0].name, "mobs/animated");
+    assert_eq!(downloads[0].hash, hash);
+    assert!(Download::parse_list("name url").is_err());
+    assert!(Download::parse_list("name url abc").is_err());
+
+    let dir = std::env::temp_dir().join("blockbench-loader-test-downloads");
+    let _ = fs::remove_dir_all(&dir);
+    let cache = Cache::new(&dir);
+
+    assert_eq!(downloads[0].fetch(&cache).unwrap(), data);
+    assert_eq!(cache.get(&hash).unwrap(), data);
+    assert!(downloads[1].fetch(&cache).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
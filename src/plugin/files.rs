@@ -0,0 +1,192 @@
+//! Finding .bbmodel files under the model paths and naming them.
+
+use super::archive;
+use crate::error::*;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+thread_local!(
+    /// model name to the file it was loaded from
+    static SOURCES: RefCell<HashMap<String, PathBuf>> = Default::default();
+);
+
+pub fn is_bbmodel(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("bbmodel"))
+}
+
+/// Every .bbmodel and .zip file in `dir` and its subfolders, sorted.
+pub fn find_models(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut models = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            models.extend(find_models(&path)?);
+        } else if (is_bbmodel(&path) || archive::is_archive(&path)) && path.is_file() {
+            models.push(path);
+        }
+    }
+
+    models.sort();
+    Ok(models)
+}
+
+/// The model path `path` is in, checking `roots` in order.
+pub fn find_root<'a>(roots: &'a [PathBuf], path: &Path) -> Option<&'a Path> {
+    let path = path.canonicalize().ok()?;
+
+    roots
+        .iter()
+        .find(|root| {
+            root.canonicalize()
+                .map(|root| path.starts_with(root))
+                .unwrap_or(false)
+        })
+        .map(|root| root.as_path())
+}
+
+/// `mobs/zombie` for `root/mobs/zombie.bbmodel`.
+pub fn model_name(root: &Path, path: &Path) -> Result<String> {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path
+            .canonicalize()?
+            .strip_prefix(root.canonicalize()?)
+            .chain_err(|| "not in model path")?
+            .to_path_buf(),
+    };
+
+    let parts = relative
+        .with_extension("")
+        .iter()
+        .map(|part| part.to_str().map(str::to_string))
+        .collect::<Option<Vec<_>>>()
+        .chain_err(|| "bad file name")?;
+
+    Ok(parts.join("/"))
+}
+
+/// The same path however the watcher or `find_models` spelled it,
+/// even for files inside archives or ones already removed.
+fn normalize(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) if !parent.as_os_str().is_empty() => {
+            normalize(parent).join(file_name)
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Remembers that `name` comes from `path`, failing if another file already has it.
+pub fn claim_name(name: &str, path: &Path) -> Result<()> {
+    let path = normalize(path);
+
+    SOURCES.with(|cell| {
+        let sources = &mut *cell.borrow_mut();
+
+        if let Some(other) = sources.get(name) {
+            ensure!(
+                *other == path,
+                "model name {:?} is already used by {:?}",
+                name,
+                other
+            );
+        } else {
+            sources.insert(name.to_string(), path);
+        }
+
+        Ok(())
+    })
+}
+
+/// Lets another file use `name`.
+pub fn release(name: &str) {
+    SOURCES.with(|cell| {
+        let sources = &mut *cell.borrow_mut();
+        sources.remove(name);
+    });
+}
+
+/// Names of the models loaded from `path` or from anything in it,
+/// like an archive or a folder.
+pub fn names_from(path: &Path) -> Vec<String> {
+    let path = normalize(path);
+
+    SOURCES.with(|cell| {
+        let mut names: Vec<String> = cell
+            .borrow()
+            .iter()
+            .filter(|(_, source)| source.starts_with(&path))
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    })
+}
+
+pub fn free() {
+    SOURCES.with(|cell| {
+        let sources = &mut *cell.borrow_mut();
+        sources.clear();
+    });
+}
+
+#[test]
+fn test_files() {
+    let dir = std::env::temp_dir().join("blockbench-loader-test-files");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("mobs/undead")).unwrap();
+    for file in &[
+        "npc.bbmodel",
+        "npc.toml",
+        "mobs/slime.bbmodel",
+        "mobs/undead/zombie.bbmodel",
+        "mobs/pack.zip",
+    ] {
+        fs::write(dir.join(file), "").unwrap();
+    }
+
+    let models = find_models(&dir).unwrap();
+    let names: Vec<String> = models
+        .iter()
+        .map(|path| model_name(&dir, path).unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        vec!["mobs/pack", "mobs/slime", "mobs/undead/zombie", "npc"]
+    );
+
+    let roots = vec![dir.join("elsewhere"), dir.clone()];
+    assert_eq!(find_root(&roots, &models[0]), Some(dir.as_path()));
+
+    let slime = dir.join("mobs/slime.bbmodel");
+    claim_name("mobs/slime", &slime).unwrap();
+    claim_name("mobs/slime", &slime).unwrap();
+    assert!(claim_name("mobs/slime", &dir.join("other/mobs/slime.bbmodel")).is_err());
+    release("mobs/slime");
+    claim_name("mobs/slime", &dir.join("other/mobs/slime.bbmodel")).unwrap();
+
+    // archived models and removed files are found however they're spelled
+    let pack = dir.join("mobs/pack.zip");
+    claim_name("mobs/a", &pack.join("a.bbmodel")).unwrap();
+    claim_name("mobs/b", &pack.join("b.bbmodel")).unwrap();
+    claim_name("npc", &dir.join("npc.bbmodel")).unwrap();
+    assert_eq!(
+        names_from(&dir.join("mobs/../mobs/pack.zip")),
+        vec!["mobs/a", "mobs/b"]
+    );
+    fs::remove_file(dir.join("npc.bbmodel")).unwrap();
+    assert_eq!(names_from(&dir.join("./npc.bbmodel")), vec!["npc"]);
+    free();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
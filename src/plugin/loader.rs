@@ -0,0 +1,353 @@
+//! Finds, watches and loads models, and hooks the plugin into the game.
+
+use super::{
+    archive::{self, ArchivedModel},
+    blockbench::Blockbench,
+    config::{self, Config, ErrorDisplay},
+    downloads, files, model, server,
+    sidecar::{self, Sidecar},
+    texture_pack,
+    worker::{self, Job},
+};
+use crate::{error::*, logger};
+use classicube_helpers::tick::TickEventHandler;
+use classicube_sys::{Chat_Add, Event_RegisterVoid, Event_UnregisterVoid, GfxEvents, OwnedString};
+use log::*;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{cell::RefCell, os::raw::c_void, ptr, sync::mpsc::channel};
+
+use std::{fs, path::Path};
+
+thread_local!(
+    static TICK_HANDLER: RefCell<Option<TickEventHandler>> = Default::default();
+);
+
+thread_local!(
+    static WATCHER: RefCell<Option<RecommendedWatcher>> = Default::default();
+);
+
+/// `root` is the model path `path` was found in.
+fn load(root: &Path, path: &Path) {
+    if let Err(e) = try_load(root, path) {
+        report_error(path, &e);
+    }
+}
+
+fn try_load(root: &Path, path: &Path) -> Result<()> {
+    let name = files::model_name(root, path)?;
+    files::claim_name(&name, path)?;
+
+    worker::send(Job::File {
+        name,
+        path: path.to_path_buf(),
+    });
+
+    Ok(())
+}
+
+/// Models in `root/mobs/pack.zip` are named as if they were in `root/mobs`.
+fn load_archive(root: &Path, path: &Path) {
+    let prefix = match files::model_name(root, path) {
+        Ok(name) => match name.rfind('/') {
+            Some(i) => name[..=i].to_string(),
+            None => String::new(),
+        },
+        Err(e) => return report_error(path, &e),
+    };
+
+    debug!("opening archive {:?}", path);
+    match archive::read_models(path, None) {
+        Ok(models) => {
+            let old_names = files::names_from(path);
+
+            let mut names = Vec::with_capacity(models.len());
+            for model in models {
+                let name = format!("{}{}", prefix, model.name);
+                load_archived(&name, model);
+                names.push(name);
+            }
+
+            // taken out of the archive since it was last read
+            for name in old_names {
+                if !names.contains(&name) {
+                    unregister(&name);
+                }
+            }
+        }
+        Err(e) => report_error(path, &e),
+    }
+}
+
+/// Returns whether `name` was claimed, even if the model then fails to load.
+pub fn load_archived(name: &str, model: ArchivedModel) -> bool {
+    if let Err(e) = files::claim_name(name, &model.source) {
+        report_error(&model.source, &e);
+        return false;
+    }
+
+    worker::send(Job::Data {
+        name: name.to_string(),
+        source: model.source,
+        data: model.data,
+        sidecar: model.sidecar,
+    });
+
+    true
+}
+
+/// Loads a model handed to us by another plugin, registered by the time this returns.
+pub fn load_model(name: &str, data: &[u8]) -> Result<()> {
+    let bb = Blockbench::parse_bbmodel(data)?;
+    files::claim_name(name, &Path::new("<api>").join(name))?;
+
+    let options = bb.options();
+    bb.register_model(name, options);
+
+    Ok(())
+}
+
+/// Like `load_model` but from a .bbmodel file and its sidecar,
+/// named after the file if `name` is `None`.
+pub fn load_model_file(name: Option<&str>, path: &Path) -> Result<()> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .chain_err(|| "bad file name")?
+            .to_string(),
+    };
+
+    let bb = Blockbench::parse_bbmodel(&fs::read(path)?)?;
+    let mut options = bb.options();
+    if let Some(sidecar_path) = Sidecar::find(path) {
+        Sidecar::load(&sidecar_path)?.apply(&mut options);
+    }
+
+    files::claim_name(&name, path)?;
+    bb.register_model(&name, options);
+
+    Ok(())
+}
+
+/// Unregisters `name` wherever it came from, letting anything load it again.
+///
+/// Returns whether it was registered, a name that failed to load is still freed.
+pub fn unregister(name: &str) -> bool {
+    let registered = model::unregister(name);
+
+    files::release(name);
+    worker::send(Job::Forget {
+        name: name.to_string(),
+    });
+
+    registered
+}
+
+pub fn report_error(path: &Path, e: &Error) {
+    match config::get().error_display {
+        ErrorDisplay::Panic => panic!("{:?}: {}", path, e),
+
+        ErrorDisplay::Chat => {
+            error!("{:?}: {}", path, e);
+
+            let text = OwnedString::new(format!("&cblockbench: {}: {}", path.display(), e));
+            unsafe {
+                Chat_Add(&text.as_cc_string());
+            }
+        }
+
+        ErrorDisplay::Log => error!("{:?}: {}", path, e),
+    }
+}
+
+pub fn init() {
+    let plugins_path = Path::new("plugins");
+    assert!(plugins_path.is_dir());
+
+    let loaded = Config::load();
+    let config = loaded.as_ref().cloned().unwrap_or_default();
+
+    logger::initialize(config.log_level, config.log_file.as_deref(), false);
+    debug!("blockbench-loader init");
+
+    if let Err(e) = loaded {
+        warn!("{}: {}, using defaults", config::CONFIG_PATH, e);
+    }
+    config::set(config.clone());
+    worker::start(config.disk_cache);
+
+    for model_path in &config.model_paths {
+        if !model_path.is_dir() {
+            if let Err(e) = fs::create_dir_all(model_path) {
+                warn!("couldn't create {:?}: {}", model_path, e);
+                continue;
+            }
+        }
+
+        match files::find_models(model_path) {
+            Ok(paths) => {
+                for path in paths {
+                    if archive::is_archive(&path) {
+                        load_archive(model_path, &path);
+                    } else {
+                        load(model_path, &path);
+                    }
+                }
+            }
+            Err(e) => warn!("couldn't search {:?}: {}", model_path, e),
+        }
+    }
+
+    // Create a channel to receive the events.
+    let (tx, rx) = channel();
+
+    if config.watch {
+        WATCHER.with(move |cell| {
+            let opt = &mut *cell.borrow_mut();
+
+            // Automatically select the best implementation for your platform.
+            // You can also access each implementation directly e.g. INotifyWatcher.
+            let mut watcher: RecommendedWatcher = Watcher::new(tx, config.debounce()).unwrap();
+
+            // Add a path to be watched. All files and directories at that path and
+            // below will be monitored for changes.
+            for model_path in &config.model_paths {
+                if let Err(e) = watcher.watch(model_path, RecursiveMode::Recursive) {
+                    warn!("couldn't watch {:?}: {}", model_path, e);
+                }
+            }
+
+            *opt = Some(watcher);
+        });
+    } else {
+        debug!("not watching for changes");
+    }
+
+    model::init();
+    texture_pack::init();
+    server::init();
+
+    unsafe {
+        Event_RegisterVoid(
+            &mut *ptr::addr_of_mut!(GfxEvents.ContextLost),
+            ptr::null_mut(),
+            Some(on_context_lost),
+        );
+        Event_RegisterVoid(
+            &mut *ptr::addr_of_mut!(GfxEvents.ContextRecreated),
+            ptr::null_mut(),
+            Some(on_context_recreated),
+        );
+    }
+
+    TICK_HANDLER.with(move |cell| {
+        let opt = &mut *cell.borrow_mut();
+
+        let mut tick_handler = TickEventHandler::new();
+        tick_handler.on(move |_| {
+            model::tick();
+            worker::tick();
+            downloads::tick();
+
+            for event in rx.try_iter() {
+                debug!("{:?}", event);
+
+                let (gone, maybe) = match event {
+                    DebouncedEvent::Create(path) => (None, Some(path)),
+                    DebouncedEvent::Write(path) => (None, Some(path)),
+                    DebouncedEvent::Rename(old, path) => (Some(old), Some(path)),
+                    // a removed sidecar still reloads its model below
+                    DebouncedEvent::Remove(path) => (Some(path.clone()), Some(path)),
+
+                    _ => (None, None),
+                };
+
+                // files, archives or whole folders that aren't there anymore
+                if let Some(path) = gone {
+                    for name in files::names_from(&path) {
+                        unregister(&name);
+                    }
+                }
+
+                if let Some(path) = maybe {
+                    let model_paths = config::get().model_paths;
+                    let root = match files::find_root(&model_paths, &path) {
+                        Some(root) => root,
+                        None => continue,
+                    };
+
+                    let extension = path.extension().and_then(|extension| extension.to_str());
+                    let is_sidecar = extension
+                        .map(|extension| sidecar::EXTENSIONS.contains(&extension))
+                        .unwrap_or(false);
+
+                    if extension == Some("bbmodel") && path.is_file() {
+                        load(root, &path);
+                    } else if archive::is_archive(&path) && path.is_file() {
+                        load_archive(root, &path);
+                    } else if is_sidecar {
+                        // a sidecar changed, reload its model
+                        let path = path.with_extension("bbmodel");
+                        if path.is_file() {
+                            load(root, &path);
+                        }
+                    }
+                }
+            }
+        });
+
+        *opt = Some(tick_handler);
+    });
+}
+
+extern "C" fn on_context_lost(_obj: *mut c_void) {
+    model::context_lost();
+}
+
+extern "C" fn on_context_recreated(_obj: *mut c_void) {
+    model::rebuild();
+}
+
+/// Reconnecting, the new server has to send its models again.
+pub fn reset() {
+    server::reset();
+    model::reset();
+    model::rebuild();
+}
+
+pub fn on_new_map_loaded() {
+    model::rebuild();
+}
+
+pub fn free() {
+    unsafe {
+        Event_UnregisterVoid(
+            &mut *ptr::addr_of_mut!(GfxEvents.ContextLost),
+            ptr::null_mut(),
+            Some(on_context_lost),
+        );
+        Event_UnregisterVoid(
+            &mut *ptr::addr_of_mut!(GfxEvents.ContextRecreated),
+            ptr::null_mut(),
+            Some(on_context_recreated),
+        );
+    }
+
+    TICK_HANDLER.with(|cell| {
+        let opt = &mut *cell.borrow_mut();
+        drop(opt.take());
+    });
+
+    WATCHER.with(|cell| {
+        let opt = &mut *cell.borrow_mut();
+        drop(opt.take());
+    });
+
+    downloads::free();
+    server::free();
+    texture_pack::free();
+    worker::stop();
+    files::free();
+    model::free();
+}
@@ -0,0 +1,348 @@
+//! Blockbench keyframe animations, started and stopped per entity through the api.
+
+use super::{ModelOptions, Rotation};
+use crate::plugin::blockbench::json::{self, LoopMode};
+use classicube_sys::{Entities, Model as CCModel};
+use log::*;
+use std::{cell::RefCell, collections::HashMap, os::raw::c_float, ptr, time::Instant};
+
+thread_local!(
+    /// entity id to the animation it's playing
+    static PLAYING: RefCell<HashMap<u8, Playing>> = Default::default();
+);
+
+struct Playing {
+    /// the entity's model when it started
+    model: *const CCModel,
+    /// lowercase
+    name: String,
+    started: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Key {
+    /// in seconds
+    time: c_float,
+    value: [c_float; 3],
+    /// hold `value` until the next key instead of blending into it
+    step: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Channel {
+    /// sorted by time
+    keys: Vec<Key>,
+}
+
+impl Channel {
+    fn sample(&self, time: c_float) -> Option<[c_float; 3]> {
+        let first = self.keys.first()?;
+        if time <= first.time {
+            return Some(first.value);
+        }
+
+        for pair in self.keys.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if time < b.time {
+                if a.step || b.time <= a.time {
+                    return Some(a.value);
+                }
+
+                let t = (time - a.time) / (b.time - a.time);
+                return Some([
+                    a.value[0] + (b.value[0] - a.value[0]) * t,
+                    a.value[1] + (b.value[1] - a.value[1]) * t,
+                    a.value[2] + (b.value[2] - a.value[2]) * t,
+                ]);
+            }
+        }
+
+        self.keys.last().map(|key| key.value)
+    }
+
+    fn map(&mut self, f: impl Fn([c_float; 3]) -> [c_float; 3]) {
+        for key in &mut self.keys {
+            key.value = f(key.value);
+        }
+    }
+}
+
+/// The keyframes of one group.
+#[derive(Debug, Clone, PartialEq)]
+struct Bone {
+    /// uuid of the group, names don't have to be unique
+    group: String,
+
+    /// in block units
+    pivot: [c_float; 3],
+
+    /// degrees, already in the same handedness as element rotations
+    rotation: Channel,
+    /// block units
+    position: Channel,
+    scale: Channel,
+}
+
+/// Where a group's vertices go at some point in an animation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoneTransform {
+    pivot: [c_float; 3],
+    rotation: Rotation,
+    translation: [c_float; 3],
+    scale: [c_float; 3],
+}
+
+impl BoneTransform {
+    /// Scales then rotates around the pivot, then moves.
+    pub fn apply(&self, point: [c_float; 3]) -> [c_float; 3] {
+        let scaled = [
+            self.pivot[0] + (point[0] - self.pivot[0]) * self.scale[0],
+            self.pivot[1] + (point[1] - self.pivot[1]) * self.scale[1],
+            self.pivot[2] + (point[2] - self.pivot[2]) * self.scale[2],
+        ];
+        let rotated = self.rotation.apply_around(scaled, self.pivot);
+
+        [
+            rotated[0] + self.translation[0],
+            rotated[1] + self.translation[1],
+            rotated[2] + self.translation[2],
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub name: String,
+    loop_mode: LoopMode,
+    /// in seconds
+    length: c_float,
+    bones: Vec<Bone>,
+}
+
+impl Animation {
+    /// `group_origins` has each group's origin in pixels, keyed by uuid.
+    pub fn from_bbmodel(
+        animation: &json::Animation,
+        group_origins: &HashMap<String, [c_float; 3]>,
+    ) -> Self {
+        let mut bones = Vec::new();
+
+        for (uuid, animator) in &animation.animators {
+            let origin = match group_origins.get(uuid) {
+                Some(origin) => *origin,
+                // effects and such
+                None => continue,
+            };
+
+            let mut bone = Bone {
+                group: uuid.clone(),
+                pivot: [origin[0] / 16.0, origin[1] / 16.0, origin[2] / 16.0],
+                rotation: Channel::default(),
+                position: Channel::default(),
+                scale: Channel::default(),
+            };
+
+            for keyframe in &animator.keyframes {
+                let channel = match keyframe.channel.as_str() {
+                    "rotation" => &mut bone.rotation,
+                    "position" => &mut bone.position,
+                    "scale" => &mut bone.scale,
+                    _ => continue,
+                };
+
+                let point = match keyframe.data_points.first() {
+                    Some(point) => point,
+                    None => continue,
+                };
+                let value = [&point.x, &point.y, &point.z].map(|molang| {
+                    molang.value().unwrap_or_else(|| {
+                        warn!(
+                            "animation {:?}: unsupported molang {:?}",
+                            animation.name, molang
+                        );
+                        0.0
+                    })
+                });
+
+                channel.keys.push(Key {
+                    time: keyframe.time,
+                    value,
+                    step: keyframe.interpolation == "step",
+                });
+            }
+
+            // Blockbench turns X and Y, and moves X, the other way for animations
+            bone.rotation.map(|[x, y, z]| [-x, -y, z]);
+            bone.position
+                .map(|[x, y, z]| [-x / 16.0, y / 16.0, z / 16.0]);
+
+            for channel in [&mut bone.rotation, &mut bone.position, &mut bone.scale] {
+                channel.keys.sort_by(|a, b| {
+                    a.time
+                        .partial_cmp(&b.time)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+            }
+
+            bones.push(bone);
+        }
+
+        Self {
+            name: animation.name.clone(),
+            loop_mode: animation.loop_mode,
+            length: animation.length,
+            bones,
+        }
+    }
+
+    pub fn apply_options(&mut self, options: &ModelOptions) {
+        let scale = options.scale;
+
+        for bone in &mut self.bones {
+            bone.pivot = options.apply(bone.pivot);
+            bone.position
+                .map(|[x, y, z]| [x * scale, y * scale, z * scale]);
+        }
+    }
+
+    /// Each animated group's transform `seconds` in, keyed by group uuid,
+    /// or `None` once an animation that doesn't loop or hold is over.
+    pub fn pose(&self, seconds: c_float) -> Option<HashMap<&str, BoneTransform>> {
+        let time = match self.loop_mode {
+            LoopMode::Loop if self.length > 0.0 => seconds % self.length,
+            LoopMode::Loop | LoopMode::Hold => seconds.min(self.length),
+            LoopMode::Once if seconds > self.length => return None,
+            LoopMode::Once => seconds,
+        };
+
+        Some(
+            self.bones
+                .iter()
+                .map(|bone| {
+                    let transform = BoneTransform {
+                        pivot: bone.pivot,
+                        rotation: Rotation::from_degrees(
+                            bone.rotation.sample(time).unwrap_or([0.0; 3]),
+                        ),
+                        translation: bone.position.sample(time).unwrap_or([0.0; 3]),
+                        scale: bone.scale.sample(time).unwrap_or([1.0; 3]),
+                    };
+
+                    (bone.group.as_str(), transform)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// `model` is the entity's model right now.
+pub fn play(entity_id: u8, model: *const CCModel, name: &str) {
+    PLAYING.with(|cell| {
+        cell.borrow_mut().insert(
+            entity_id,
+            Playing {
+                model,
+                name: name.to_lowercase(),
+                started: Instant::now(),
+            },
+        );
+    });
+}
+
+pub fn stop(entity_id: u8) {
+    PLAYING.with(|cell| cell.borrow_mut().remove(&entity_id));
+}
+
+/// The animation `entity_id` is playing and for how many seconds.
+pub fn playing(entity_id: u8) -> Option<(String, c_float)> {
+    PLAYING.with(|cell| {
+        cell.borrow().get(&entity_id).map(|playing| {
+            (
+                playing.name.clone(),
+                playing.started.elapsed().as_secs_f32(),
+            )
+        })
+    })
+}
+
+/// Stops the animations of entities that went away or changed model.
+pub fn stop_changed() {
+    PLAYING.with(|cell| {
+        cell.borrow_mut().retain(|entity_id, playing| {
+            let entity = unsafe { Entities.List[*entity_id as usize] };
+            !entity.is_null() && ptr::eq(unsafe { (*entity).Model }, playing.model)
+        })
+    });
+}
+
+pub fn reset() {
+    PLAYING.with(|cell| cell.borrow_mut().clear());
+}
+
+#[test]
+fn test_animation() {
+    let json: json::Animation = serde_json::from_str(
+        r#"{
+            "name": "animation.wave",
+            "loop": "loop",
+            "length": 2,
+            "animators": {
+                "arm-uuid": {
+                    "name": "Arm",
+                    "keyframes": [
+                        { "channel": "rotation", "time": 1, "data_points": [{ "x": 0, "y": 0, "z": "90" }] },
+                        { "channel": "rotation", "time": 0, "data_points": [{ "x": 0, "y": 0, "z": 0 }] },
+                        { "channel": "position", "time": 0, "data_points": [{ "x": "16", "y": 0, "z": "math.sin(1)" }], "interpolation": "step" },
+                        { "channel": "sound", "time": 0, "data_points": [{ "x": 0, "y": 0, "z": 0 }] }
+                    ]
+                },
+                "effects": { "name": "Effects", "keyframes": [] }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    let mut origins = HashMap::new();
+    origins.insert("arm-uuid".to_string(), [0.0, 16.0, 0.0]);
+    let animation = Animation::from_bbmodel(&json, &origins);
+    assert_eq!(animation.bones.len(), 1);
+
+    let close = |a: [c_float; 3], b: [c_float; 3]| (0..3).all(|i| (a[i] - b[i]).abs() < 0.0001);
+
+    // halfway to 90 degrees around Z, around the pivot one block up
+    let pose = animation.pose(0.5).unwrap();
+    let arm = pose["arm-uuid"];
+    let rotated =
+        Rotation::from_degrees([0.0, 0.0, 45.0]).apply_around([1.0, 1.0, 0.0], [0.0, 1.0, 0.0]);
+    // moved a block the other way on X
+    assert!(close(
+        arm.apply([1.0, 1.0, 0.0]),
+        [rotated[0] - 1.0, rotated[1], rotated[2]]
+    ));
+
+    // loops back around
+    assert_eq!(animation.pose(2.5).unwrap()["arm-uuid"], arm);
+
+    let mut once = animation.clone();
+    once.loop_mode = LoopMode::Once;
+    assert!(once.pose(2.5).is_none());
+
+    let mut scaled = animation.clone();
+    scaled.apply_options(&ModelOptions {
+        scale: 2.0,
+        ..Default::default()
+    });
+    let scaled_arm = scaled.pose(0.0).unwrap()["arm-uuid"];
+    assert!(close(scaled_arm.apply([0.0, 2.0, 0.0]), [-2.0, 2.0, 0.0]));
+
+    play(3, ptr::null(), "Animation.Wave");
+    assert_eq!(playing(3).unwrap().0, "animation.wave");
+    stop(3);
+    assert!(playing(3).is_none());
+
+    // entity 3 isn't there anymore
+    play(3, ptr::null(), "animation.wave");
+    stop_changed();
+    assert!(playing(3).is_none());
+    reset();
+}
@@ -0,0 +1,130 @@
+use super::{ModelOptions, Rotation};
+use crate::plugin::blockbench::json;
+use classicube_sys::{Entity, Matrix, Vec4};
+use std::os::raw::c_float;
+
+const HAND_NAMES: [&str; 4] = ["hand", "righthand", "rightitem", "helditem"];
+
+/// A named point on the model, from a Blockbench locator, null object or group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachmentPoint {
+    pub name: String,
+
+    /// in block units, relative to the entity's feet
+    pub position: [c_float; 3],
+    pub rotation: Rotation,
+}
+
+/// Where an attachment point is in the world for some entity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub position: [c_float; 3],
+    pub rotation: Rotation,
+}
+
+impl AttachmentPoint {
+    pub fn from_bbmodel_locator(e: &json::Locator) -> Self {
+        Self {
+            name: e.name.clone(),
+            position: [
+                e.position[0] / 16.0,
+                e.position[1] / 16.0,
+                e.position[2] / 16.0,
+            ],
+            rotation: Rotation::from_degrees(e.rotation),
+        }
+    }
+
+    pub fn from_bbmodel_group(group: &json::Group) -> Self {
+        Self {
+            name: group.name.clone(),
+            position: [
+                group.origin[0] / 16.0,
+                group.origin[1] / 16.0,
+                group.origin[2] / 16.0,
+            ],
+            rotation: Rotation::from_degrees([0.0, 0.0, 0.0]),
+        }
+    }
+
+    pub fn apply_options(&mut self, options: &ModelOptions) {
+        self.position = options.apply(self.position);
+    }
+
+    /// Where held blocks go, "hand", "right_hand", "RightHand" or bedrock's "rightItem".
+    pub fn is_hand(&self) -> bool {
+        let name: String = self
+            .name
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+
+        HAND_NAMES.contains(&name.as_str())
+    }
+
+    /// Follows the entity around the same way `Entity_GetTransform` does:
+    /// scaled, then rotated around Z, X and Y, then moved to its position.
+    pub fn transform(&self, entity: &Entity) -> Transform {
+        let scale = [
+            entity.ModelScale.X,
+            entity.ModelScale.Y,
+            entity.ModelScale.Z,
+        ];
+
+        let entity_rotation = Rotation::from_degrees([0.0, -entity.RotY, 0.0])
+            * Rotation::from_degrees([-entity.RotX, 0.0, 0.0])
+            * Rotation::from_degrees([0.0, 0.0, -entity.RotZ]);
+
+        let scaled = [
+            self.position[0] * scale[0],
+            self.position[1] * scale[1],
+            self.position[2] * scale[2],
+        ];
+        let [x, y, z] = entity_rotation.apply(scaled);
+
+        Transform {
+            position: [
+                entity.Position.X + x,
+                entity.Position.Y + y,
+                entity.Position.Z + z,
+            ],
+            rotation: entity_rotation * self.rotation,
+        }
+    }
+}
+
+impl Transform {
+    /// ClassiCube's matrices are row major and multiply row vectors from the left.
+    pub fn to_matrix(self) -> Matrix {
+        let m = self.rotation.matrix();
+        let [x, y, z] = self.position;
+
+        Matrix {
+            row1: Vec4 {
+                X: m[0][0],
+                Y: m[1][0],
+                Z: m[2][0],
+                W: 0.0,
+            },
+            row2: Vec4 {
+                X: m[0][1],
+                Y: m[1][1],
+                Z: m[2][1],
+                W: 0.0,
+            },
+            row3: Vec4 {
+                X: m[0][2],
+                Y: m[1][2],
+                Z: m[2][2],
+                W: 0.0,
+            },
+            row4: Vec4 {
+                X: x,
+                Y: y,
+                Z: z,
+                W: 1.0,
+            },
+        }
+    }
+}
@@ -1,122 +1,454 @@
-use crate::plugin::blockbench;
-use classicube_sys::{
-    cc_uint16, BoxDesc_XQuad, BoxDesc_YQuad, BoxDesc_ZQuad, ModelPart, ModelPart_Init,
-    Model_DrawPart, Model_DrawRotate, Models, MODEL_BOX_VERTICES,
-};
-use std::{
-    mem,
-    os::raw::{c_float, c_int},
-};
+#[cfg(feature = "plugin")]
+use super::part::{draw_model_part, model_part_range};
+use super::{part::Vertex, ModelOptions, Rotation};
+use crate::plugin::blockbench::json;
+#[cfg(feature = "plugin")]
+use classicube_sys::{Entity, ModelPart, PackedCol};
+#[cfg(feature = "plugin")]
+use std::ops::Range;
+use std::os::raw::c_float;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FaceUv {
+    /// `[u1, v1, u2, v2]` in texture pixels, `u1, v1` goes on the face's
+    /// top left corner when looking at it from outside
+    pub uv: [c_float; 4],
+
+    /// clockwise, in degrees
+    pub rotation: u16,
+}
 
 #[derive(Debug, Default)]
 pub struct Cube {
+    pub name: String,
+
     pub from: [c_float; 3],
     pub to: [c_float; 3],
 
-    pub tex_x: c_int,
-    pub tex_y: c_int,
-
-    pub tex_sides_w: c_int,
-    pub tex_body_w: c_int,
-    pub tex_body_h: c_int,
+    /// up, down, north, south, east, west, the order `Models.Cols` shades them in
+    pub faces: [FaceUv; 6],
 
     /// pivot point/origin
     pub pivot_origin: [c_float; 3],
 
-    /// angled rotation, passed to Model_DrawRotate
-    pub rot: Option<[c_float; 3]>,
+    /// rotation around `pivot_origin`, baked into the vertices by `quads`
+    pub rotation: Option<Rotation>,
+
+    /// part of the head, turned with the entity's pitch around this point
+    pub head_pivot: Option<[c_float; 3]>,
 
-    model_part: Option<ModelPart>,
+    /// drawn unlit, for things that glow
+    pub emissive: bool,
+
+    /// covers partially transparent pixels, drawn blended after opaque parts
+    pub translucent: bool,
+
+    /// names of the groups this is in, outermost first
+    pub groups: Vec<String>,
+    /// uuids of the same groups
+    pub group_uuids: Vec<String>,
+
+    #[cfg(feature = "plugin")]
+    pub(super) model_part: Option<ModelPart>,
 }
 
+#[cfg(feature = "plugin")]
 impl Cube {
-    #[rustfmt::skip]
-    pub fn make_part(&mut self) {
-        unsafe {
-            let m = &mut *Models.Active;
-            let mut part: ModelPart = mem::zeroed();
-
-            let x1 = self.from[0];
-            let y1 = self.from[1];
-            let z1 = self.from[2];
-
-            let x2 = self.to[0];
-            let y2 = self.to[1];
-            let z2 = self.to[2];
-
-            let x = self.tex_x;
-            let y = self.tex_y;
-
-            let sidesW = self.tex_sides_w;
-            let bodyW = self.tex_body_w;
-            let bodyH = self.tex_body_h;
-
-            let rotX = self.pivot_origin[0];
-            let rotY = self.pivot_origin[1];
-            let rotZ = self.pivot_origin[2];
-
-            BoxDesc_YQuad(m, x + sidesW,                  y,          bodyW, sidesW, x1, x2, z2, z1, y2, 1);  /* top */
-            BoxDesc_YQuad(m, x + sidesW + bodyW,          y,          bodyW, sidesW, x2, x1, z2, z1, y1, 0);  /* bottom */
-            BoxDesc_ZQuad(m, x + sidesW,                  y + sidesW, bodyW,  bodyH, x1, x2, y1, y2, z1, 1);  /* front */
-            BoxDesc_ZQuad(m, x + sidesW + bodyW + sidesW, y + sidesW, bodyW,  bodyH, x2, x1, y1, y2, z2, 1);  /* back */
-            BoxDesc_XQuad(m, x,                           y + sidesW, sidesW, bodyH, z1, z2, y1, y2, x2, 1);  /* left */
-            BoxDesc_XQuad(m, x + sidesW + bodyW,          y + sidesW, sidesW, bodyH, z2, z1, y1, y2, x1, 1);  /* right */
-
-            ModelPart_Init(
-                &mut part,
-                m.index as cc_uint16 - MODEL_BOX_VERTICES as cc_uint16,
-                MODEL_BOX_VERTICES as cc_uint16,
-                rotX,
-                rotY,
-                rotZ
-            );
-
-            self.model_part = Some(part);
-        }
+    pub fn vertex_range(&self) -> Option<Range<usize>> {
+        self.model_part.as_ref().map(model_part_range)
     }
 
     /// must call `make_part` first!
-    pub fn draw(&mut self) {
-        if let Some(rot) = self.rot {
-            unsafe {
-                // TODO head last arg bool
-                Model_DrawRotate(rot[0], rot[1], rot[2], self.model_part.as_mut().unwrap(), 0);
-            }
+    pub fn draw(&mut self, entity: &Entity, tint: Option<PackedCol>) {
+        draw_model_part(
+            self.model_part.as_mut().unwrap(),
+            self.head_pivot.is_some(),
+            self.emissive,
+            tint,
+            entity,
+        );
+    }
+}
+
+impl Cube {
+    /// Where a point of this cube ends up once rotated, in block units.
+    pub fn transform(&self, point: [c_float; 3]) -> [c_float; 3] {
+        if let Some(rotation) = self.rotation {
+            rotation.apply_around(point, self.pivot_origin)
         } else {
-            unsafe {
-                Model_DrawPart(self.model_part.as_mut().unwrap());
+            point
+        }
+    }
+
+    /// Scales and moves the cube, its rotation stays the same.
+    pub fn apply_options(&mut self, options: &ModelOptions) {
+        self.from = options.apply(self.from);
+        self.to = options.apply(self.to);
+        self.pivot_origin = options.apply(self.pivot_origin);
+        self.head_pivot = self.head_pivot.map(|pivot| options.apply(pivot));
+    }
+
+    /// One quad per face in `faces` order, each going
+    /// top left, top right, bottom right, bottom left.
+    #[rustfmt::skip]
+    pub fn quads(&self) -> [[Vertex; 4]; 6] {
+        let [x1, y1, z1] = self.from;
+        let [x2, y2, z2] = self.to;
+
+        let corners = [
+            [[x1, y2, z1], [x2, y2, z1], [x2, y2, z2], [x1, y2, z2]], /* up */
+            [[x1, y1, z2], [x2, y1, z2], [x2, y1, z1], [x1, y1, z1]], /* down */
+            [[x2, y2, z1], [x1, y2, z1], [x1, y1, z1], [x2, y1, z1]], /* north */
+            [[x1, y2, z2], [x2, y2, z2], [x2, y1, z2], [x1, y1, z2]], /* south */
+            [[x2, y2, z2], [x2, y2, z1], [x2, y1, z1], [x2, y1, z2]], /* east */
+            [[x1, y2, z1], [x1, y2, z2], [x1, y1, z2], [x1, y1, z1]], /* west */
+        ];
+
+        let mut quads = [[Vertex { position: [0.0; 3], uv: [0.0; 2] }; 4]; 6];
+        for (quad, (corners, face)) in quads.iter_mut().zip(corners.iter().zip(&self.faces)) {
+            let [u1, v1, u2, v2] = face.uv;
+            let mut uvs = [[u1, v1], [u2, v1], [u2, v2], [u1, v2]];
+            uvs.rotate_right((face.rotation / 90) as usize % 4);
+
+            for ((vertex, corner), uv) in quad.iter_mut().zip(corners).zip(&uvs) {
+                vertex.position = self.transform(*corner);
+                vertex.uv = *uv;
             }
         }
+
+        quads
     }
 }
 
 impl Cube {
-    pub fn from_bbmodel_element(e: blockbench::json::Element) -> Self {
+    pub fn from_bbmodel_element(e: json::Cube, box_uv: bool, texture_size: [c_float; 2]) -> Self {
         let from = [e.from[0] / 16.0, e.from[1] / 16.0, e.from[2] / 16.0];
         let to = [e.to[0] / 16.0, e.to[1] / 16.0, e.to[2] / 16.0];
 
-        let tex_x = e.uv_offset.map(|a| a[0]).unwrap_or(0) as c_int;
-        let tex_y = e.uv_offset.map(|a| a[1]).unwrap_or(0) as c_int;
+        let size = [
+            e.to[0] - e.from[0],
+            e.to[1] - e.from[1],
+            e.to[2] - e.from[2],
+        ];
 
-        let tex_sides_w = (e.faces.east.uv[2] as c_int - e.faces.east.uv[0] as c_int).abs();
-        let tex_body_w = (e.faces.up.uv[2] as c_int - e.faces.up.uv[0] as c_int).abs();
-        let tex_body_h = (e.faces.east.uv[3] as c_int - e.faces.east.uv[1] as c_int).abs();
+        let faces = if box_uv {
+            box_uv_faces(e.uv_offset.unwrap_or([0.0, 0.0]), size, e.mirror_uv)
+        } else {
+            let json::Faces {
+                north,
+                east,
+                south,
+                west,
+                up,
+                down,
+            } = &e.faces;
+
+            let mut faces = [FaceUv::default(); 6];
+            for (face, json_face) in faces.iter_mut().zip(&[up, down, north, south, east, west]) {
+                face.uv = json_face.uv;
+                face.rotation = json_face.rotation;
+            }
+
+            match e.autouv {
+                1 => auto_uv_faces(&mut faces, size, texture_size),
+                2 => relative_auto_uv_faces(&mut faces, e.from, e.to, texture_size),
+                _ => {}
+            }
+
+            faces
+        };
 
         let pivot_origin = [e.origin[0] / 16.0, e.origin[1] / 16.0, e.origin[2] / 16.0];
 
-        let rot = e.rotation.map(|r| [r[0], r[1], r[2]]);
+        let rotation = e
+            .rotation
+            .filter(|r| r.iter().any(|angle| *angle != 0.0))
+            .map(|r| {
+                if e.rescale {
+                    Rotation::from_degrees_rescaled(r)
+                } else {
+                    Rotation::from_degrees(r)
+                }
+            });
 
         Self {
+            name: e.name,
             from,
             to,
-            tex_x,
-            tex_y,
-            tex_sides_w,
-            tex_body_w,
-            tex_body_h,
+            faces,
             pivot_origin,
-            rot,
+            rotation,
+            head_pivot: None,
+            emissive: false,
+            translucent: false,
+            groups: Vec::new(),
+            group_uuids: Vec::new(),
+            #[cfg(feature = "plugin")]
             model_part: None,
         }
     }
 }
+
+/// Blockbench's box uv layout, unfolded from `uv_offset` like a minecraft skin:
+///
+/// ```text
+///        up    down
+/// east  north  west  south
+/// ```
+///
+/// Blockbench floors the cube's size first so a 2.5 wide cube gets 2 pixels,
+/// anything thinner than a pixel gets one here so it doesn't vanish.
+#[rustfmt::skip]
+pub fn box_uv_faces(uv_offset: [c_float; 2], size: [c_float; 3], mirror: bool) -> [FaceUv; 6] {
+    let [x, y] = uv_offset;
+
+    // so that 2.9999999 from float error still counts as 3
+    const EPSILON: c_float = 0.000_01;
+    let floor = |size: c_float| {
+        if size > 0.0 && size < 1.0 {
+            1.0
+        } else {
+            (size + EPSILON).floor()
+        }
+    };
+    let [w, h, d] = size.map(floor);
+
+    let uvs = [
+        [x + d + w,         y + d, x + d,             y        ], /* up */
+        [x + d + w + w,     y,     x + d + w,         y + d    ], /* down */
+        [x + d,             y + d, x + d + w,         y + d + h], /* north */
+        [x + d + w + d,     y + d, x + d + w + d + w, y + d + h], /* south */
+        [x,                 y + d, x + d,             y + d + h], /* east */
+        [x + d + w,         y + d, x + d + w + d,     y + d + h], /* west */
+    ];
+
+    let mut faces = [FaceUv::default(); 6];
+    for (face, uv) in faces.iter_mut().zip(&uvs) {
+        face.uv = *uv;
+
+        // flipped horizontally, with east and west trading places below
+        if mirror {
+            face.uv.swap(0, 2);
+        }
+    }
+
+    if mirror {
+        faces.swap(4, 5);
+    }
+
+    faces
+}
+
+/// `autouv: 1`, faces keep where they start on the texture but are
+/// resized to match the cube.
+fn auto_uv_faces(faces: &mut [FaceUv; 6], size: [c_float; 3], texture_size: [c_float; 2]) {
+    let [w, h, d] = size;
+
+    for (face, [mut size_u, mut size_v]) in
+        faces
+            .iter_mut()
+            .zip(&[[w, d], [w, d], [w, h], [w, h], [d, h], [d, h]])
+    {
+        if face.rotation == 90 || face.rotation == 270 {
+            std::mem::swap(&mut size_u, &mut size_v);
+        }
+
+        let mut start_u = face.uv[0];
+        let mut start_v = face.uv[1];
+        let mut end_u = start_u + size_u.min(texture_size[0]);
+        let mut end_v = start_v + size_v.min(texture_size[1]);
+
+        // slide back in if we went off the texture
+        if end_u > texture_size[0] {
+            start_u -= end_u - texture_size[0];
+            end_u = texture_size[0];
+        }
+        if end_v > texture_size[1] {
+            start_v -= end_v - texture_size[1];
+            end_v = texture_size[1];
+        }
+
+        face.uv = [start_u.max(0.0), start_v.max(0.0), end_u, end_v];
+    }
+}
+
+/// `autouv: 2`, faces are mapped from where the cube is in the block.
+#[rustfmt::skip]
+fn relative_auto_uv_faces(
+    faces: &mut [FaceUv; 6],
+    from: [c_float; 3],
+    to: [c_float; 3],
+    texture_size: [c_float; 2],
+) {
+    let [x1, y1, z1] = from;
+    let [x2, y2, z2] = to;
+
+    let uvs = [
+        [x1,        z1,        x2,        z2       ], /* up */
+        [x1,        16.0 - z2, x2,        16.0 - z1], /* down */
+        [16.0 - x2, 16.0 - y2, 16.0 - x1, 16.0 - y1], /* north */
+        [x1,        16.0 - y2, x2,        16.0 - y1], /* south */
+        [16.0 - z2, 16.0 - y2, 16.0 - z1, 16.0 - y1], /* east */
+        [z1,        16.0 - y2, z2,        16.0 - y1], /* west */
+    ];
+
+    for (face, uv) in faces.iter_mut().zip(&uvs) {
+        face.uv = [
+            uv[0].max(0.0).min(texture_size[0]),
+            uv[1].max(0.0).min(texture_size[1]),
+            uv[2].max(0.0).min(texture_size[0]),
+            uv[3].max(0.0).min(texture_size[1]),
+        ];
+    }
+}
+
+#[cfg(test)]
+fn cube_json(autouv: usize, faces_uv: [[c_float; 4]; 6]) -> json::Cube {
+    let mut faces = serde_json::Map::new();
+    for (name, uv) in ["up", "down", "north", "south", "east", "west"]
+        .iter()
+        .zip(&faces_uv)
+    {
+        faces.insert(
+            name.to_string(),
+            serde_json::json!({ "uv": uv, "texture": 0 }),
+        );
+    }
+
+    serde_json::from_value(serde_json::json!({
+        "name": "cube",
+        "from": [0, 0, 0],
+        "to": [2.5, 3, 4],
+        "autouv": autouv,
+        "color": 0,
+        "origin": [0, 0, 0],
+        "faces": faces,
+        "uuid": "",
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_fractional_uv() {
+    let uvs = [
+        [0.5, 0.25, 3.0, 3.25],
+        [3.0, 0.25, 7.0, 3.25],
+        [7.0, 0.25, 9.5, 3.25],
+        [9.5, 0.25, 13.5, 3.25],
+        [0.0, 4.0, 2.5, 8.0],
+        [2.5, 4.0, 5.0, 8.0],
+    ];
+    let cube = Cube::from_bbmodel_element(cube_json(0, uvs), false, [16.0, 16.0]);
+
+    for (quad, uv) in cube.quads().iter().zip(&uvs) {
+        assert_eq!(quad[0].uv, [uv[0], uv[1]]);
+        assert_eq!(quad[2].uv, [uv[2], uv[3]]);
+
+        // still exact once packed
+        for vertex in quad {
+            for coord in &vertex.uv {
+                let packed = (coord * super::UV_PRECISION).round();
+                assert_eq!(packed / super::UV_PRECISION, *coord);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_auto_uv() {
+    // sizes are wrong on purpose, autouv recomputes them from the cube
+    let uvs = [
+        [0.0, 0.0, 1.0, 1.0],
+        [4.0, 0.0, 5.0, 1.0],
+        [8.0, 0.0, 9.0, 1.0],
+        [14.0, 0.0, 15.0, 1.0],
+        [0.0, 8.0, 1.0, 9.0],
+        [4.0, 8.0, 5.0, 9.0],
+    ];
+    let cube = Cube::from_bbmodel_element(cube_json(1, uvs), false, [16.0, 16.0]);
+
+    let faces: Vec<[c_float; 4]> = cube.faces.iter().map(|face| face.uv).collect();
+    assert_eq!(
+        faces,
+        vec![
+            [0.0, 0.0, 2.5, 4.0],
+            [4.0, 0.0, 6.5, 4.0],
+            [8.0, 0.0, 10.5, 3.0],
+            // pushed back onto the texture
+            [13.5, 0.0, 16.0, 3.0],
+            [0.0, 8.0, 4.0, 11.0],
+            [4.0, 8.0, 8.0, 11.0],
+        ]
+    );
+
+    // relative to where the cube sits in the block
+    let cube = Cube::from_bbmodel_element(cube_json(2, uvs), false, [16.0, 16.0]);
+    assert_eq!(cube.faces[2].uv, [13.5, 13.0, 16.0, 16.0]);
+    assert_eq!(cube.faces[0].uv, [0.0, 0.0, 2.5, 4.0]);
+}
+
+#[test]
+fn test_box_uv_matches_box_desc() {
+    // same corners and uvs that ClassiCube's BoxDesc_BuildBox would make
+    let cube = Cube {
+        from: [0.0, 0.0, 0.0],
+        to: [8.0, 12.0, 4.0],
+        faces: box_uv_faces([16.0, 32.0], [8.0, 12.0, 4.0], false),
+        ..Default::default()
+    };
+    let quads = cube.quads();
+
+    // front, texture's top left on the right
+    assert_eq!(quads[2][0].position, [8.0, 12.0, 0.0]);
+    assert_eq!(quads[2][0].uv, [20.0, 36.0]);
+
+    // top, texture's top left on the back right
+    assert_eq!(quads[0][2].position, [8.0, 12.0, 4.0]);
+    assert_eq!(quads[0][2].uv, [20.0, 32.0]);
+
+    // bottom, texture's top left on the back right
+    assert_eq!(quads[1][1].position, [8.0, 0.0, 4.0]);
+    assert_eq!(quads[1][1].uv, [28.0, 32.0]);
+
+    // left, texture's top left at the back
+    assert_eq!(quads[4][0].position, [8.0, 12.0, 4.0]);
+    assert_eq!(quads[4][0].uv, [16.0, 36.0]);
+}
+
+#[test]
+fn test_box_uv_fractional_size() {
+    let faces = box_uv_faces([0.0, 0.0], [2.5, 3.0, 0.0], false);
+
+    // 2 x 3 x 0
+    assert_eq!(faces[2].uv, [0.0, 0.0, 2.0, 3.0]);
+    assert_eq!(faces[4].uv, [0.0, 0.0, 0.0, 3.0]);
+    assert_eq!(faces[3].uv, [2.0, 0.0, 4.0, 3.0]);
+    assert_eq!(faces[0].uv, [2.0, 0.0, 0.0, 0.0]);
+
+    // float error doesn't lose a pixel
+    let faces = box_uv_faces([0.0, 0.0], [3.975 - -2.025, 1.0, 1.0], false);
+    assert_eq!(faces[2].uv, [1.0, 1.0, 7.0, 2.0]);
+}
+
+#[test]
+fn test_box_uv_subpixel_size() {
+    // 2 x 1 x 1 pixels for a 2 x 0.5 x 0.25 flap
+    let faces = box_uv_faces([0.0, 0.0], [2.0, 0.5, 0.25], false);
+    assert_eq!(faces[0].uv, [3.0, 1.0, 1.0, 0.0]);
+    assert_eq!(faces[1].uv, [5.0, 0.0, 3.0, 1.0]);
+    assert_eq!(faces[2].uv, [1.0, 1.0, 3.0, 2.0]);
+    assert_eq!(faces[3].uv, [4.0, 1.0, 6.0, 2.0]);
+    assert_eq!(faces[4].uv, [0.0, 1.0, 1.0, 2.0]);
+    assert_eq!(faces[5].uv, [3.0, 1.0, 4.0, 2.0]);
+}
+
+#[test]
+fn test_box_uv_mirror() {
+    let faces = box_uv_faces([0.0, 0.0], [8.0, 12.0, 4.0], false);
+    let mirrored = box_uv_faces([0.0, 0.0], [8.0, 12.0, 4.0], true);
+
+    assert_eq!(mirrored[2].uv, [12.0, 4.0, 4.0, 16.0]);
+    assert_eq!(mirrored[4].uv, [16.0, 4.0, 12.0, 16.0]);
+    assert_eq!(mirrored[5].uv, [4.0, 4.0, 0.0, 16.0]);
+    assert_eq!(mirrored[4].uv[0], faces[5].uv[2]);
+}
@@ -0,0 +1,172 @@
+//! A model's vertices as plain data, laid out the way they're copied into
+//! `ModelVertex`es, so what we generate can be checked without the game.
+
+use super::{part::Vertex, Part};
+use std::{ops::Range, os::raw::c_float};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geometry {
+    /// every part's quads one after the other, 4 vertices each
+    pub vertices: Vec<Vertex>,
+    /// in the same order as the parts they were made from
+    pub parts: Vec<PartGeometry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartGeometry {
+    pub name: String,
+    /// of `Geometry::vertices`
+    pub range: Range<usize>,
+    /// `range` split into one `ModelPart` each, see `Part::chunks`
+    pub chunks: Vec<Range<usize>>,
+    /// in block units
+    pub pivot: [c_float; 3],
+    pub head: bool,
+    /// outermost first
+    pub groups: Vec<String>,
+}
+
+impl Geometry {
+    pub fn new(parts: &[Part]) -> Self {
+        let mut vertices = Vec::new();
+
+        let parts = parts
+            .iter()
+            .map(|part| {
+                let start = vertices.len();
+                let chunks = part
+                    .chunks()
+                    .into_iter()
+                    .map(|chunk| {
+                        let chunk_start = vertices.len();
+                        for quad in chunk {
+                            vertices.extend_from_slice(&quad);
+                        }
+                        chunk_start..vertices.len()
+                    })
+                    .collect();
+
+                PartGeometry {
+                    name: part.name().to_string(),
+                    range: start..vertices.len(),
+                    chunks,
+                    pivot: part.pivot(),
+                    head: part.is_head(),
+                    groups: part.groups().to_vec(),
+                }
+            })
+            .collect();
+
+        Self { vertices, parts }
+    }
+}
+
+#[cfg(test)]
+fn load(path: &str, options: &super::ModelOptions) -> Geometry {
+    use crate::plugin::blockbench::Blockbench;
+
+    let data = std::fs::read(path).unwrap();
+    let bb = Blockbench::parse_bbmodel(&data).unwrap();
+    Geometry::new(&bb.parts(options))
+}
+
+#[test]
+fn test_geometry_player() {
+    use super::ModelOptions;
+
+    // 6 quads of 4
+    const CUBE_VERTICES: usize = 24;
+
+    let geometry = load("tests/Player.bbmodel", &ModelOptions::default());
+
+    // 12 cubes, 6 quads each, packed one after the other
+    assert_eq!(geometry.parts.len(), 12);
+    assert_eq!(geometry.vertices.len(), 12 * CUBE_VERTICES);
+    let mut next = 0;
+    for part in &geometry.parts {
+        assert_eq!(part.range.start, next);
+        assert_eq!(part.range.len(), CUBE_VERTICES);
+        next = part.range.end;
+    }
+
+    // the head turns around its group's pivot
+    let heads: Vec<&PartGeometry> = geometry.parts.iter().filter(|part| part.head).collect();
+    assert_eq!(heads.len(), 2);
+    for head in heads {
+        assert_eq!(head.pivot, [3.0 / 16.0, 12.0 / 16.0, 3.0 / 16.0]);
+        assert_eq!(head.groups, vec!["Head".to_string()]);
+    }
+
+    // every uv lands on the 64x64 texture
+    for vertex in &geometry.vertices {
+        assert!(vertex.uv.iter().all(|uv| (0.0..=64.0).contains(uv)));
+    }
+
+    // standing on the ground, 2 blocks tall
+    for vertex in &geometry.vertices {
+        assert!((0.0..=2.0).contains(&vertex.position[1]));
+    }
+
+    // twice as big and a block up
+    let options = ModelOptions {
+        scale: 2.0,
+        offset: [0.0, 1.0, 0.0],
+        ..Default::default()
+    };
+    let scaled = load("tests/Player.bbmodel", &options);
+    for (vertex, scaled) in geometry.vertices.iter().zip(&scaled.vertices) {
+        assert_eq!(scaled.position, options.apply(vertex.position));
+        assert_eq!(scaled.uv, vertex.uv);
+    }
+}
+
+#[test]
+fn test_geometry_cube() {
+    let geometry = load("tests/mesh.bbmodel", &Default::default());
+
+    let cube = geometry
+        .parts
+        .iter()
+        .find(|part| part.name == "cube")
+        .unwrap();
+    assert_eq!(cube.chunks, vec![cube.range.clone()]);
+
+    // 4x4x4 pixels around the origin, box uv from the top left corner
+    let [x1, y1, z1] = [-0.125, 0.0, -0.125];
+    let [x2, y2, z2] = [0.125, 0.25, 0.125];
+    #[rustfmt::skip]
+    let expected = [
+        [([x1, y2, z1], [8.0, 4.0]), ([x2, y2, z1], [4.0, 4.0]), ([x2, y2, z2], [4.0, 0.0]), ([x1, y2, z2], [8.0, 0.0])], /* up */
+        [([x1, y1, z2], [12.0, 0.0]), ([x2, y1, z2], [8.0, 0.0]), ([x2, y1, z1], [8.0, 4.0]), ([x1, y1, z1], [12.0, 4.0])], /* down */
+        [([x2, y2, z1], [4.0, 4.0]), ([x1, y2, z1], [8.0, 4.0]), ([x1, y1, z1], [8.0, 8.0]), ([x2, y1, z1], [4.0, 8.0])], /* north */
+        [([x1, y2, z2], [12.0, 4.0]), ([x2, y2, z2], [16.0, 4.0]), ([x2, y1, z2], [16.0, 8.0]), ([x1, y1, z2], [12.0, 8.0])], /* south */
+        [([x2, y2, z2], [0.0, 4.0]), ([x2, y2, z1], [4.0, 4.0]), ([x2, y1, z1], [4.0, 8.0]), ([x2, y1, z2], [0.0, 8.0])], /* east */
+        [([x1, y2, z1], [8.0, 4.0]), ([x1, y2, z2], [12.0, 4.0]), ([x1, y1, z2], [12.0, 8.0]), ([x1, y1, z1], [8.0, 8.0])], /* west */
+    ];
+
+    let quads: Vec<&[Vertex]> = geometry.vertices[cube.range.clone()].chunks(4).collect();
+    assert_eq!(quads.len(), expected.len());
+    for (face, (quad, expected)) in quads.iter().zip(&expected).enumerate() {
+        for (vertex, (position, uv)) in quad.iter().zip(expected) {
+            assert_eq!(vertex.position, *position, "face {}", face);
+            assert_eq!(vertex.uv, *uv, "face {}", face);
+        }
+    }
+}
+
+#[test]
+fn test_geometry_mesh() {
+    let geometry = load("tests/mesh.bbmodel", &Default::default());
+
+    let mesh = geometry
+        .parts
+        .iter()
+        .find(|part| part.name == "mesh")
+        .unwrap();
+    // the pyramid's tip is straight above its pivot
+    let tip = geometry.vertices[mesh.range.clone()]
+        .iter()
+        .map(|vertex| vertex.position)
+        .fold([0.0; 3], |a, b| if b[1] > a[1] { b } else { a });
+    assert_eq!(tip[0], mesh.pivot[0]);
+}
@@ -0,0 +1,105 @@
+use super::Transform;
+use classicube_sys::{
+    BlockID, Entities, Entity, Gfx, Gfx_LoadMatrix, Inventory, Matrix, MatrixType_MATRIX_VIEW,
+    Matrix_Mul, Matrix_RotateX, Matrix_RotateY, Matrix_RotateZ, Matrix_Scale, Matrix_Translate,
+    Model_Get, Model_UpdateVB, Models, OwnedString, BLOCK_AIR, ENTITIES_SELF_ID,
+};
+use std::{mem, os::raw::c_float, ptr};
+
+/// How big a held block is compared to the entity, about the size
+/// of the block in the humanoid's hand.
+const HELD_BLOCK_SCALE: c_float = 0.4;
+
+/// The block `entity` is holding: its `ModelBlock` if another plugin set one,
+/// otherwise our own selected block since that's the only inventory we know.
+pub fn held_block(entity: &Entity) -> Option<BlockID> {
+    unsafe {
+        let block = if entity.ModelBlock != BLOCK_AIR as BlockID {
+            entity.ModelBlock
+        } else if ptr::eq(Entities.List[ENTITIES_SELF_ID as usize], entity) {
+            Inventory.Table[(Inventory.Offset + Inventory.SelectedIndex) as usize]
+        } else {
+            return None;
+        };
+
+        if block == BLOCK_AIR as BlockID {
+            None
+        } else {
+            Some(block)
+        }
+    }
+}
+
+/// Draws `block` with the "block" model's `Draw`, centered on `hand`.
+///
+/// Called from inside our own `Draw`, so what the game set up for us is put back after.
+pub unsafe fn draw_held_block(entity: &mut Entity, block: BlockID, hand: Transform) {
+    let name = OwnedString::new("block");
+    let block_model = Model_Get(&name.as_cc_string());
+    if block_model.is_null() {
+        return;
+    }
+    let draw = match (*block_model).Draw {
+        Some(draw) => draw,
+        None => return,
+    };
+
+    let scale = [
+        entity.ModelScale.X * HELD_BLOCK_SCALE,
+        entity.ModelScale.Y * HELD_BLOCK_SCALE,
+        entity.ModelScale.Z * HELD_BLOCK_SCALE,
+    ];
+
+    // the block model stands on its origin, move it down to center it on the hand
+    let [dx, dy, dz] = hand.rotation.apply([0.0, -scale[1] / 2.0, 0.0]);
+    let [rot_x, rot_y, rot_z] = hand.rotation.to_yxz_degrees();
+
+    // like Entity_GetTransform: scaled, rotated around Z, X then Y, then moved
+    let mut transform: Matrix = mem::zeroed();
+    let mut step: Matrix = mem::zeroed();
+    Matrix_Scale(&mut transform, scale[0], scale[1], scale[2]);
+    for (rotate, degrees) in [
+        (Matrix_RotateZ as unsafe extern "C" fn(_, _), rot_z),
+        (Matrix_RotateX, rot_x),
+        (Matrix_RotateY, rot_y),
+    ] {
+        rotate(&mut step, degrees.to_radians());
+        let left = transform;
+        Matrix_Mul(&mut transform, &left, &step);
+    }
+    Matrix_Translate(
+        &mut step,
+        hand.position[0] + dx,
+        hand.position[1] + dy,
+        hand.position[2] + dz,
+    );
+    let left = transform;
+    Matrix_Mul(&mut transform, &left, &step);
+    let left = transform;
+    Matrix_Mul(&mut transform, &left, ptr::addr_of!(Gfx.View));
+
+    // our vertices go out before the block model reuses the buffer
+    Model_UpdateVB();
+
+    let active = Models.Active;
+    let (u_scale, v_scale) = (Models.uScale, Models.vScale);
+    let cols = Models.Cols;
+    let model_block = entity.ModelBlock;
+
+    Models.Active = block_model;
+    (*block_model).index = 0;
+    entity.ModelBlock = block;
+    Gfx_LoadMatrix(MatrixType_MATRIX_VIEW, &transform);
+
+    draw(entity);
+
+    // back to where `Model_Render` left us
+    let mut ours: Matrix = mem::zeroed();
+    Matrix_Mul(&mut ours, &entity.Transform, ptr::addr_of!(Gfx.View));
+    Gfx_LoadMatrix(MatrixType_MATRIX_VIEW, &ours);
+    entity.ModelBlock = model_block;
+    Models.Cols = cols;
+    Models.uScale = u_scale;
+    Models.vScale = v_scale;
+    Models.Active = active;
+}
@@ -0,0 +1,461 @@
+#[cfg(feature = "plugin")]
+use super::part::{draw_model_part, model_part_range};
+use super::{part::Vertex, ModelOptions, Rotation};
+use crate::plugin::blockbench;
+#[cfg(feature = "plugin")]
+use classicube_sys::{Entity, ModelPart, PackedCol};
+use log::*;
+#[cfg(feature = "plugin")]
+use std::ops::Range;
+use std::os::raw::c_float;
+
+/// A Blockbench 4 mesh, made into quads since that's all ClassiCube draws.
+///
+/// Triangles become quads with their last vertex doubled,
+/// faces with more than 4 vertices are cut into triangles.
+#[derive(Debug, Default)]
+pub struct Mesh {
+    pub name: String,
+
+    pub quads: Vec<[Vertex; 4]>,
+
+    /// pivot point/origin
+    pub pivot_origin: [c_float; 3],
+
+    /// part of the head, turned with the entity's pitch around this point
+    pub head_pivot: Option<[c_float; 3]>,
+
+    /// drawn unlit, for things that glow
+    pub emissive: bool,
+
+    /// covers partially transparent pixels, drawn blended after opaque parts
+    pub translucent: bool,
+
+    /// names of the groups this is in, outermost first
+    pub groups: Vec<String>,
+    /// uuids of the same groups
+    pub group_uuids: Vec<String>,
+
+    /// one per chunk
+    #[cfg(feature = "plugin")]
+    pub(super) model_parts: Vec<ModelPart>,
+}
+
+#[cfg(feature = "plugin")]
+impl Mesh {
+    pub fn vertex_range(&self) -> Option<Range<usize>> {
+        let first = self.model_parts.first()?;
+        let last = self.model_parts.last()?;
+        Some(model_part_range(first).start..model_part_range(last).end)
+    }
+
+    /// must call `make_part` first!
+    pub fn draw(&mut self, entity: &Entity, tint: Option<PackedCol>) {
+        assert!(!self.model_parts.is_empty());
+        for model_part in &mut self.model_parts {
+            draw_model_part(
+                model_part,
+                self.head_pivot.is_some(),
+                self.emissive,
+                tint,
+                entity,
+            );
+        }
+    }
+}
+
+impl Mesh {
+    /// `quads` split into parts of at most 6, since the game shades a part's
+    /// n-th quad with `Models.Cols[n]`.
+    ///
+    /// Each quad goes in the slot its normal faces, empty slots get a quad of no size.
+    pub fn chunks(&self) -> Vec<Vec<[Vertex; 4]>> {
+        let center = self.center();
+
+        let mut slots: [Vec<[Vertex; 4]>; 6] = Default::default();
+        for quad in &self.quads {
+            slots[shade_slot(quad, center)].push(*quad);
+        }
+
+        let empty = [Vertex {
+            position: self.pivot_origin,
+            uv: [0.0, 0.0],
+        }; 4];
+
+        let count = slots.iter().map(Vec::len).max().unwrap_or(0);
+        (0..count)
+            .map(|i| {
+                // no filler needed after the last slot with a quad
+                let used = slots.iter().rposition(|slot| slot.len() > i).unwrap() + 1;
+                slots[..used]
+                    .iter()
+                    .map(|slot| slot.get(i).copied().unwrap_or(empty))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// average of every vertex
+    fn center(&self) -> [c_float; 3] {
+        let mut sum = [0.0; 3];
+        for vertex in self.quads.iter().flatten() {
+            for (sum, position) in sum.iter_mut().zip(&vertex.position) {
+                *sum += position;
+            }
+        }
+        let count = (self.quads.len() * 4).max(1) as c_float;
+        sum.map(|sum| sum / count)
+    }
+}
+
+/// The `Models.Cols` slot for the way `quad` faces, away from `center`:
+/// up, down, north, south, east, west like `Cube::faces`.
+fn shade_slot(quad: &[Vertex; 4], center: [c_float; 3]) -> usize {
+    let [a, b, c] = [quad[0].position, quad[1].position, quad[2].position];
+    let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let mut normal = [
+        ab[1] * ac[2] - ab[2] * ac[1],
+        ab[2] * ac[0] - ab[0] * ac[2],
+        ab[0] * ac[1] - ab[1] * ac[0],
+    ];
+
+    // Blockbench winds faces either way
+    let outward: c_float = (0..3)
+        .map(|i| {
+            let middle = quad.iter().map(|v| v.position[i]).sum::<c_float>() / 4.0;
+            normal[i] * (middle - center[i])
+        })
+        .sum();
+    if outward < 0.0 {
+        normal = normal.map(|n| -n);
+    }
+
+    let [x, y, z] = normal;
+
+    if y.abs() >= x.abs() && y.abs() >= z.abs() {
+        if y >= 0.0 {
+            0
+        } else {
+            1
+        }
+    } else if z.abs() >= x.abs() {
+        if z < 0.0 {
+            2
+        } else {
+            3
+        }
+    } else if x >= 0.0 {
+        4
+    } else {
+        5
+    }
+}
+
+impl Mesh {
+    /// Scales and moves every vertex.
+    pub fn apply_options(&mut self, options: &ModelOptions) {
+        for quad in &mut self.quads {
+            for vertex in quad.iter_mut() {
+                vertex.position = options.apply(vertex.position);
+            }
+        }
+        self.pivot_origin = options.apply(self.pivot_origin);
+        self.head_pivot = self.head_pivot.map(|pivot| options.apply(pivot));
+    }
+}
+
+impl Mesh {
+    pub fn from_bbmodel_element(e: blockbench::json::Mesh) -> Self {
+        let pivot_origin = [e.origin[0] / 16.0, e.origin[1] / 16.0, e.origin[2] / 16.0];
+        let rotation = Rotation::from_degrees(e.rotation.unwrap_or([0.0, 0.0, 0.0]));
+
+        let mut quads = Vec::with_capacity(e.faces.len());
+        'faces: for face in e.faces.values() {
+            let mut vertices = Vec::with_capacity(face.vertices.len());
+            for key in &face.vertices {
+                let (position, uv) = match (e.vertices.get(key), face.uv.get(key)) {
+                    (Some(position), Some(uv)) => (position, uv),
+                    _ => {
+                        warn!(
+                            "mesh {:?}: skipping face with missing vertex or uv {:?}",
+                            e.name, key
+                        );
+                        continue 'faces;
+                    }
+                };
+
+                let [x, y, z] = rotation.apply(*position);
+                vertices.push(Vertex {
+                    position: [
+                        pivot_origin[0] + x / 16.0,
+                        pivot_origin[1] + y / 16.0,
+                        pivot_origin[2] + z / 16.0,
+                    ],
+                    uv: *uv,
+                });
+            }
+
+            match vertices.len() {
+                3 => quads.push([vertices[0], vertices[1], vertices[2], vertices[2]]),
+                4 => {
+                    let order = loop_order(&[
+                        vertices[0].position,
+                        vertices[1].position,
+                        vertices[2].position,
+                        vertices[3].position,
+                    ]);
+                    quads.push([
+                        vertices[order[0]],
+                        vertices[order[1]],
+                        vertices[order[2]],
+                        vertices[order[3]],
+                    ]);
+                }
+
+                // points and edges have nothing to draw
+                0..=2 => {}
+
+                _ => {
+                    let positions: Vec<[c_float; 3]> =
+                        vertices.iter().map(|vertex| vertex.position).collect();
+                    let order = winding_order(&positions);
+
+                    // a fan from the first vertex, fine for the convex faces Blockbench makes
+                    for pair in order[1..].windows(2) {
+                        let [a, b, c] = [vertices[order[0]], vertices[pair[0]], vertices[pair[1]]];
+                        quads.push([a, b, c, c]);
+                    }
+                }
+            }
+        }
+
+        Self {
+            name: e.name,
+            quads,
+            pivot_origin,
+            head_pivot: None,
+            emissive: false,
+            translucent: false,
+            groups: Vec::new(),
+            group_uuids: Vec::new(),
+            #[cfg(feature = "plugin")]
+            model_parts: Vec::new(),
+        }
+    }
+}
+
+/// Blockbench stores quad vertices in any order, find the one that goes
+/// around the edge instead of crossing over a diagonal.
+///
+/// For a convex quad that's the order with the shortest perimeter.
+fn loop_order(points: &[[c_float; 3]; 4]) -> [usize; 4] {
+    fn distance(a: [c_float; 3], b: [c_float; 3]) -> c_float {
+        ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+    }
+
+    let mut best = [0, 1, 2, 3];
+    let mut best_perimeter = c_float::INFINITY;
+    for order in &[[0, 1, 2, 3], [0, 1, 3, 2], [0, 2, 1, 3]] {
+        let perimeter: c_float = (0..4)
+            .map(|i| distance(points[order[i]], points[order[(i + 1) % 4]]))
+            .sum();
+
+        if perimeter < best_perimeter {
+            best = *order;
+            best_perimeter = perimeter;
+        }
+    }
+
+    best
+}
+
+/// `points` of a flat face in order around its center.
+fn winding_order(points: &[[c_float; 3]]) -> Vec<usize> {
+    fn sub(a: [c_float; 3], b: [c_float; 3]) -> [c_float; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+    fn cross(a: [c_float; 3], b: [c_float; 3]) -> [c_float; 3] {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    }
+    fn dot(a: [c_float; 3], b: [c_float; 3]) -> c_float {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    let count = points.len() as c_float;
+    let mut center = [0.0; 3];
+    for point in points {
+        for i in 0..3 {
+            center[i] += point[i] / count;
+        }
+    }
+
+    // the face's normal from the two spokes furthest from parallel
+    let spokes: Vec<[c_float; 3]> = points.iter().map(|point| sub(*point, center)).collect();
+    let mut normal = [0.0; 3];
+    for a in &spokes {
+        for b in &spokes {
+            let n = cross(*a, *b);
+            if dot(n, n) > dot(normal, normal) {
+                normal = n;
+            }
+        }
+    }
+
+    let u = spokes[0];
+    let v = cross(normal, u);
+    // starting from the first point
+    let angle = |i: usize| {
+        let angle = dot(spokes[i], v).atan2(dot(spokes[i], u));
+        if angle < 0.0 {
+            angle + 2.0 * std::f32::consts::PI
+        } else {
+            angle
+        }
+    };
+
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|a, b| {
+        angle(*a)
+            .partial_cmp(&angle(*b))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    order
+}
+
+#[test]
+fn test_winding_order() {
+    // a hexagon in the XZ plane, shuffled
+    let hexagon: Vec<[c_float; 3]> = [0, 3, 1, 5, 2, 4]
+        .iter()
+        .map(|i| {
+            let angle = *i as c_float * std::f32::consts::PI / 3.0;
+            [angle.cos(), 0.0, angle.sin()]
+        })
+        .collect();
+
+    let order = winding_order(&hexagon);
+    assert_eq!(order[0], 0);
+    let mut around: Vec<usize> = order.iter().map(|i| [0, 3, 1, 5, 2, 4][*i]).collect();
+    // either way around is fine
+    if around[1] != 1 {
+        around[1..].reverse();
+    }
+    assert_eq!(around, vec![0, 1, 2, 3, 4, 5]);
+
+    let face = |vertices: &[&str]| blockbench::json::MeshFace {
+        vertices: vertices.iter().map(|key| key.to_string()).collect(),
+        uv: vertices
+            .iter()
+            .map(|key| (key.to_string(), [0.0, 0.0]))
+            .collect(),
+        texture: Some(0),
+    };
+    let keys = ["a", "b", "c", "d", "e", "f"];
+    let e = blockbench::json::Mesh {
+        name: "hexagon".to_string(),
+        origin: [0.0, 0.0, 0.0],
+        rotation: None,
+        vertices: keys
+            .iter()
+            .zip(&hexagon)
+            .map(|(key, point)| (key.to_string(), *point))
+            .collect(),
+        faces: vec![
+            ("top".to_string(), face(&keys)),
+            ("broken".to_string(), face(&["a", "b", "missing"])),
+        ]
+        .into_iter()
+        .collect(),
+        uuid: String::new(),
+    };
+
+    // 4 triangles, nothing for the face with a missing vertex
+    let mesh = Mesh::from_bbmodel_element(e);
+    assert_eq!(mesh.quads.len(), 4);
+    assert!(mesh.quads.iter().all(|quad| quad[2] == quad[3]));
+}
+
+#[test]
+fn test_loop_order() {
+    let square = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 1.0],
+        [1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0],
+    ];
+    assert_eq!(loop_order(&square), [0, 2, 1, 3]);
+
+    let already = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 0.0, 1.0],
+        [0.0, 0.0, 1.0],
+    ];
+    assert_eq!(loop_order(&already), [0, 1, 2, 3]);
+}
+
+#[test]
+fn test_chunks() {
+    use super::Cube;
+
+    // a 2x1x1 box out of two cubes without the faces between them,
+    // shuffled and wound either way
+    let halves = [
+        Cube {
+            to: [1.0, 1.0, 1.0],
+            ..Default::default()
+        },
+        Cube {
+            from: [1.0, 0.0, 0.0],
+            to: [2.0, 1.0, 1.0],
+            ..Default::default()
+        },
+    ];
+    let inside = [4, 5];
+    let mut mesh = Mesh::default();
+    for (cube, inside) in halves.iter().zip(inside) {
+        for (face, quad) in cube.quads().iter().enumerate().rev() {
+            if face == inside {
+                continue;
+            }
+            let mut quad = *quad;
+            if face % 2 == 0 {
+                quad.reverse();
+            }
+            mesh.quads.push(quad);
+        }
+    }
+    assert_eq!(mesh.quads.len(), 10);
+
+    let sorted = |mut quad: [Vertex; 4]| {
+        quad.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap());
+        quad
+    };
+
+    // each slot gets a face pointing its way
+    let [left, right] = [halves[0].quads(), halves[1].quads()];
+    let chunks = mesh.chunks();
+    assert_eq!(chunks.len(), 2);
+    for (chunk, expected) in chunks.iter().zip(&[
+        vec![left[0], left[1], left[2], left[3], right[4], left[5]],
+        vec![right[0], right[1], right[2], right[3]],
+    ]) {
+        assert_eq!(chunk.len(), expected.len());
+        for (quad, expected) in chunk.iter().zip(expected) {
+            assert_eq!(sorted(*quad), sorted(*expected));
+        }
+    }
+
+    // an open box gets a filler where its top would be
+    mesh.quads = left[1..].to_vec();
+    let chunks = mesh.chunks();
+    assert_eq!(chunks, vec![[&[chunks[0][0]], &left[1..]].concat()]);
+    assert!(chunks[0][0]
+        .iter()
+        .all(|vertex| vertex.position == mesh.pivot_origin));
+}
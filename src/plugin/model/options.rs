@@ -0,0 +1,57 @@
+use std::{collections::HashMap, os::raw::c_float};
+
+/// Per-model settings that Blockbench can't express.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelOptions {
+    /// multiplies the size of everything, so a model authored at
+    /// 16 pixels per block can be made bigger or smaller
+    pub scale: c_float,
+
+    /// in block units, moves everything after scaling
+    pub offset: [c_float; 3],
+
+    /// in block units above the feet, instead of the "nametag" attachment point
+    pub name_y: Option<c_float>,
+
+    /// in block units above the feet, instead of the "eye" attachment point
+    pub eye_y: Option<c_float>,
+
+    /// elements in groups with this name turn with the entity's pitch
+    pub head_group: String,
+
+    /// whether an entity's skin replaces our texture
+    pub uses_skin: bool,
+
+    /// in pixels, instead of a scaled humanoid's
+    pub collision_size: Option<[c_float; 3]>,
+
+    /// lowercase alias to the name of one of the model's animations,
+    /// so "walk" can play "animation.zombie.walk"
+    pub animations: HashMap<String, String>,
+}
+
+impl Default for ModelOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            offset: [0.0, 0.0, 0.0],
+            name_y: None,
+            eye_y: None,
+            head_group: "head".to_string(),
+            uses_skin: true,
+            collision_size: None,
+            animations: HashMap::new(),
+        }
+    }
+}
+
+impl ModelOptions {
+    /// Scales then moves a point in block units.
+    pub fn apply(&self, point: [c_float; 3]) -> [c_float; 3] {
+        [
+            point[0] * self.scale + self.offset[0],
+            point[1] * self.scale + self.offset[1],
+            point[2] * self.scale + self.offset[2],
+        ]
+    }
+}
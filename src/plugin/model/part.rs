@@ -0,0 +1,403 @@
+#[cfg(feature = "plugin")]
+use super::{tint::tint_col, Geometry};
+use super::{Cube, Mesh, ModelOptions};
+#[cfg(feature = "plugin")]
+use classicube_sys::{
+    cc_uint16, Entity, ModelPart, ModelPart_Init, ModelVertex, Model_DrawPart, Model_DrawRotate,
+    Model_UpdateVB, Models, PackedCol,
+};
+use std::os::raw::c_float;
+#[cfg(feature = "plugin")]
+use std::{mem, ops::Range};
+
+/// Vertex uvs are stored in fractions of a texture pixel so that fractional
+/// Blockbench uvs survive being packed into `ModelVertex`'s integers.
+pub const UV_PRECISION: c_float = 16.0;
+
+/// What a cube takes, the game's `MODEL_BOX_VERTICES`.
+pub const CUBE_VERTICES: usize = 6 * 4;
+
+/// something we will never reach so that `update_existing` has enough room to grow
+pub const MAX_VERTICES: usize = 256 * CUBE_VERTICES;
+
+/// opaque white
+#[cfg(feature = "plugin")]
+const FULLBRIGHT: PackedCol = 0xFFFF_FFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    /// in block units, already rotated
+    pub position: [c_float; 3],
+
+    /// in texture pixels
+    pub uv: [c_float; 2],
+}
+
+#[derive(Debug)]
+pub enum Part {
+    Cube(Cube),
+    Mesh(Mesh),
+}
+
+impl Part {
+    pub fn name(&self) -> &str {
+        match self {
+            Part::Cube(cube) => &cube.name,
+            Part::Mesh(mesh) => &mesh.name,
+        }
+    }
+
+    pub fn set_head_pivot(&mut self, head_pivot: Option<[c_float; 3]>) {
+        match self {
+            Part::Cube(cube) => cube.head_pivot = head_pivot,
+            Part::Mesh(mesh) => mesh.head_pivot = head_pivot,
+        }
+    }
+
+    pub fn set_emissive(&mut self, emissive: bool) {
+        match self {
+            Part::Cube(cube) => cube.emissive = emissive,
+            Part::Mesh(mesh) => mesh.emissive = emissive,
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        match self {
+            Part::Cube(cube) => cube.emissive,
+            Part::Mesh(mesh) => mesh.emissive,
+        }
+    }
+
+    pub fn set_translucent(&mut self, translucent: bool) {
+        match self {
+            Part::Cube(cube) => cube.translucent = translucent,
+            Part::Mesh(mesh) => mesh.translucent = translucent,
+        }
+    }
+
+    pub fn is_translucent(&self) -> bool {
+        match self {
+            Part::Cube(cube) => cube.translucent,
+            Part::Mesh(mesh) => mesh.translucent,
+        }
+    }
+
+    /// Whether any pixel under this part's uvs, in any animation frame,
+    /// is neither fully opaque nor fully transparent.
+    ///
+    /// `pixels` are BGRA, `frame_height` rows per frame.
+    pub fn covers_partial_alpha(&self, pixels: &[u8], width: usize, frame_height: usize) -> bool {
+        let quads = self.quads();
+
+        let height = pixels.len() / 4 / width;
+        quads.iter().any(|quad| {
+            let (mut u1, mut v1) = (c_float::INFINITY, c_float::INFINITY);
+            let (mut u2, mut v2) = (c_float::NEG_INFINITY, c_float::NEG_INFINITY);
+            for vertex in quad {
+                u1 = u1.min(vertex.uv[0]);
+                v1 = v1.min(vertex.uv[1]);
+                u2 = u2.max(vertex.uv[0]);
+                v2 = v2.max(vertex.uv[1]);
+            }
+
+            let x1 = (u1.floor().max(0.0) as usize).min(width);
+            let x2 = (u2.ceil().max(0.0) as usize).min(width);
+            let y1 = (v1.floor().max(0.0) as usize).min(frame_height);
+            let y2 = (v2.ceil().max(0.0) as usize).min(frame_height);
+
+            (0..height).step_by(frame_height).any(|frame_y| {
+                (y1..y2).any(|y| {
+                    (x1..x2).any(|x| {
+                        let alpha = pixels[((frame_y + y) * width + x) * 4 + 3];
+                        alpha != 0 && alpha != 255
+                    })
+                })
+            })
+        })
+    }
+
+    /// Must be called after `set_head_pivot`.
+    pub fn apply_options(&mut self, options: &ModelOptions) {
+        match self {
+            Part::Cube(cube) => cube.apply_options(options),
+            Part::Mesh(mesh) => mesh.apply_options(options),
+        }
+    }
+
+    /// `uuids` are of the same groups as `groups`.
+    pub fn set_groups(&mut self, groups: Vec<String>, uuids: Vec<String>) {
+        match self {
+            Part::Cube(cube) => {
+                cube.groups = groups;
+                cube.group_uuids = uuids;
+            }
+            Part::Mesh(mesh) => {
+                mesh.groups = groups;
+                mesh.group_uuids = uuids;
+            }
+        }
+    }
+
+    pub fn groups(&self) -> &[String] {
+        match self {
+            Part::Cube(cube) => &cube.groups,
+            Part::Mesh(mesh) => &mesh.groups,
+        }
+    }
+
+    pub fn group_uuids(&self) -> &[String] {
+        match self {
+            Part::Cube(cube) => &cube.group_uuids,
+            Part::Mesh(mesh) => &mesh.group_uuids,
+        }
+    }
+
+    pub fn is_head(&self) -> bool {
+        match self {
+            Part::Cube(cube) => cube.head_pivot.is_some(),
+            Part::Mesh(mesh) => mesh.head_pivot.is_some(),
+        }
+    }
+
+    /// In block units, options already applied.
+    pub fn quads(&self) -> Vec<[Vertex; 4]> {
+        match self {
+            Part::Cube(cube) => cube.quads().to_vec(),
+            Part::Mesh(mesh) => mesh.quads.clone(),
+        }
+    }
+
+    /// `quads` grouped into the `ModelPart`s the game draws, at most 6 quads each.
+    pub fn chunks(&self) -> Vec<Vec<[Vertex; 4]>> {
+        match self {
+            Part::Cube(cube) => vec![cube.quads().to_vec()],
+            Part::Mesh(mesh) => mesh.chunks(),
+        }
+    }
+
+    /// What the game turns this part around, the head's pivot for head parts.
+    pub fn pivot(&self) -> [c_float; 3] {
+        match self {
+            Part::Cube(cube) => cube.head_pivot.unwrap_or(cube.pivot_origin),
+            Part::Mesh(mesh) => mesh.head_pivot.unwrap_or(mesh.pivot_origin),
+        }
+    }
+}
+
+#[cfg(feature = "plugin")]
+impl Part {
+    /// Where `make_part` put this part's vertices.
+    pub fn vertex_range(&self) -> Option<Range<usize>> {
+        match self {
+            Part::Cube(cube) => cube.vertex_range(),
+            Part::Mesh(mesh) => mesh.vertex_range(),
+        }
+    }
+
+    /// Points the game at the model's vertices, one range per chunk.
+    pub fn make_part(&mut self, ranges: &[Range<usize>]) {
+        let pivot = self.pivot();
+        let mut model_parts = ranges
+            .iter()
+            .map(|range| make_model_part(range.clone(), pivot));
+        match self {
+            Part::Cube(cube) => cube.model_part = model_parts.next(),
+            Part::Mesh(mesh) => mesh.model_parts = model_parts.collect(),
+        }
+    }
+
+    /// must call `make_part` first!
+    pub fn draw(&mut self, entity: &Entity, tint: Option<PackedCol>) {
+        match self {
+            Part::Cube(cube) => cube.draw(entity, tint),
+            Part::Mesh(mesh) => mesh.draw(entity, tint),
+        }
+    }
+}
+
+/// Set on a `ModelVertex` uv on the far edge of its quad's texture region,
+/// which the game pulls in a little so the next pixel over doesn't bleed in.
+#[cfg(feature = "plugin")]
+pub const UV_MAX: cc_uint16 = 0x8000;
+
+#[cfg(feature = "plugin")]
+impl Vertex {
+    /// `u_max`/`v_max` flag the uv like `BoxDesc_*Quad` does.
+    pub fn to_model_vertex(self, u_max: bool, v_max: bool) -> ModelVertex {
+        let flag = |max: bool| if max { UV_MAX } else { 0 };
+
+        ModelVertex {
+            X: self.position[0],
+            Y: self.position[1],
+            Z: self.position[2],
+            U: (self.uv[0] * UV_PRECISION).round() as cc_uint16 | flag(u_max),
+            V: (self.uv[1] * UV_PRECISION).round() as cc_uint16 | flag(v_max),
+        }
+    }
+}
+
+/// `geometry`'s vertices as the game wants them, with the far edges of each quad's uvs flagged.
+#[cfg(feature = "plugin")]
+fn model_vertices(geometry: &Geometry) -> Vec<ModelVertex> {
+    let mut model_vertices = Vec::with_capacity(geometry.vertices.len());
+
+    for quad in geometry.vertices.chunks(4) {
+        let extent = |i: usize| {
+            let min = quad
+                .iter()
+                .map(|v| v.uv[i])
+                .fold(c_float::INFINITY, c_float::min);
+            let max = quad
+                .iter()
+                .map(|v| v.uv[i])
+                .fold(c_float::NEG_INFINITY, c_float::max);
+            (min, max)
+        };
+        let (u1, u2) = extent(0);
+        let (v1, v2) = extent(1);
+
+        for vertex in quad {
+            model_vertices.push(
+                vertex
+                    .to_model_vertex(u2 > u1 && vertex.uv[0] == u2, v2 > v1 && vertex.uv[1] == v2),
+            );
+        }
+    }
+
+    model_vertices
+}
+
+/// Lays out `parts` with `Geometry` and copies it into `Models.Active`'s vertices.
+#[cfg(feature = "plugin")]
+pub unsafe fn make_parts(parts: &mut [Part]) {
+    let m = &mut *Models.Active;
+    let geometry = Geometry::new(parts);
+
+    let start = m.index as usize;
+    for (i, vertex) in model_vertices(&geometry).into_iter().enumerate() {
+        *m.vertices.add(start + i) = vertex;
+    }
+    m.index = (start + geometry.vertices.len()) as _;
+
+    for (part, laid_out) in parts.iter_mut().zip(&geometry.parts) {
+        let ranges: Vec<Range<usize>> = laid_out
+            .chunks
+            .iter()
+            .map(|range| start + range.start..start + range.end)
+            .collect();
+        part.make_part(&ranges);
+    }
+}
+
+#[cfg(feature = "plugin")]
+fn make_model_part(range: Range<usize>, pivot: [c_float; 3]) -> ModelPart {
+    unsafe {
+        let mut part: ModelPart = mem::zeroed();
+        ModelPart_Init(
+            &mut part,
+            range.start as cc_uint16,
+            range.len() as cc_uint16,
+            pivot[0],
+            pivot[1],
+            pivot[2],
+        );
+        part
+    }
+}
+
+#[cfg(feature = "plugin")]
+pub fn model_part_range(part: &ModelPart) -> Range<usize> {
+    let offset = part.offset as usize;
+    offset..offset + part.count as usize
+}
+
+/// Head parts follow the entity's pitch, emissive parts ignore lighting
+/// and tinted parts are multiplied by `tint`.
+#[cfg(feature = "plugin")]
+pub fn draw_model_part(
+    part: &mut ModelPart,
+    head: bool,
+    emissive: bool,
+    tint: Option<PackedCol>,
+    entity: &Entity,
+) {
+    unsafe {
+        // meshes can outgrow the engine's vertex buffer, flush it early
+        if (*Models.Active).index as usize + part.count as usize > Models.MaxVertices as usize {
+            Model_UpdateVB();
+        }
+
+        // vertex colors come from `Models.Cols`, which `Model_SetupState` lit and shaded
+        let lit_cols = Models.Cols;
+        if emissive {
+            Models.Cols = [FULLBRIGHT; 6];
+        }
+        if let Some(tint) = tint {
+            let mut cols = Models.Cols;
+            for col in cols.iter_mut() {
+                *col = tint_col(*col, tint);
+            }
+            Models.Cols = cols;
+        }
+
+        if head {
+            Model_DrawRotate(-entity.Pitch.to_radians(), 0.0, 0.0, part, 1);
+        } else {
+            Model_DrawPart(part);
+        }
+
+        Models.Cols = lit_cols;
+    }
+}
+
+#[test]
+#[allow(clippy::field_reassign_with_default)]
+fn test_covers_partial_alpha() {
+    use super::cube::box_uv_faces;
+
+    // box uv of a 1x1x1 cube covers the 4x2 pixels at the top left
+    let mut cube = Cube::default();
+    cube.to = [1.0 / 16.0, 1.0 / 16.0, 1.0 / 16.0];
+    cube.faces = box_uv_faces([0.0, 0.0], [1.0, 1.0, 1.0], false);
+    let part = Part::Cube(cube);
+
+    let pixel = |x: usize, y: usize| (y * 8 + x) * 4 + 3;
+
+    // two 8x8 frames
+    let mut pixels = vec![255; 8 * 16 * 4];
+    assert!(!part.covers_partial_alpha(&pixels, 8, 8));
+
+    // fully transparent is just a cut-out
+    pixels[pixel(1, 1)] = 0;
+    assert!(!part.covers_partial_alpha(&pixels, 8, 8));
+
+    // not under any face
+    pixels[pixel(5, 5)] = 128;
+    assert!(!part.covers_partial_alpha(&pixels, 8, 8));
+
+    // in the second frame
+    pixels[pixel(2, 8 + 1)] = 128;
+    assert!(part.covers_partial_alpha(&pixels, 8, 8));
+}
+
+#[test]
+#[cfg(feature = "plugin")]
+fn test_model_vertex() {
+    use classicube_sys::MODEL_BOX_VERTICES;
+
+    // validate counts cubes without the game
+    assert_eq!(CUBE_VERTICES, MODEL_BOX_VERTICES as usize);
+
+    let vertex = Vertex {
+        position: [0.5, 1.0, -0.25],
+        uv: [1.5, 63.0],
+    };
+    let model_vertex = vertex.to_model_vertex(false, true);
+
+    assert_eq!(
+        [model_vertex.X, model_vertex.Y, model_vertex.Z],
+        vertex.position
+    );
+    assert_eq!(model_vertex.U, 24);
+    assert_eq!(model_vertex.V, (63 * 16) | UV_MAX);
+}
@@ -0,0 +1,706 @@
+//! Our models registered with the game, drawn through the `Model` callbacks.
+
+use super::{
+    animation, held_block, part, tint, Animation, AttachmentPoint, BoneTransform, ModelOptions,
+    Part, TextureAnimation, Transform, MAX_VERTICES, UV_PRECISION,
+};
+use classicube_sys::{
+    Bitmap, Entities, Entity, EntityEvents, Entity_SetModel, Event_RegisterInt,
+    Event_UnregisterInt, GfxResourceID, Gfx_SetAlphaBlending, Gfx_SetDepthWrite, Model as CCModel,
+    ModelTex, ModelVertex, Model_ApplyTexture, Model_Init, Model_Register, Model_RetAABB,
+    Model_RetSize, Model_Unregister, Model_UpdateVB, Models, OwnedGfxTexture, OwnedString,
+    SKIN_TYPE_SKIN_64x64,
+};
+use log::*;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::CString,
+    mem,
+    os::raw::{c_float, c_int, c_void},
+    pin::Pin,
+    ptr, slice,
+};
+
+// just so we keep them alive
+thread_local!(
+    static MODELS: RefCell<HashMap<*const CCModel, Model>> = Default::default();
+);
+
+/// Where the attachment point `name` of `entity`'s model is in the world,
+/// if it's one of our models and has one.
+pub fn attachment_transform(entity: &Entity, name: &str) -> Option<Transform> {
+    MODELS.with(|cell| {
+        let models = &*cell.borrow();
+
+        models
+            .get(&(entity.Model as *const CCModel))?
+            .attachment(name)
+            .map(|attachment| attachment.transform(entity))
+    })
+}
+
+/// Names of every registered model.
+pub fn names() -> Vec<String> {
+    MODELS.with(|cell| {
+        let models = &*cell.borrow();
+        models.values().map(|model| model.name.clone()).collect()
+    })
+}
+
+/// Plays the animation `name`, or the one the sidecar aliases as `name`, on the entity
+/// with id `entity_id`, if it's using one of our models and that has such an animation.
+pub fn play_animation(entity_id: u8, name: &str) -> bool {
+    let found = MODELS.with(|cell| {
+        let models = &*cell.borrow();
+        let entity = unsafe { Entities.List[entity_id as usize] };
+        if entity.is_null() {
+            return None;
+        }
+
+        models
+            .get(&(unsafe { (*entity).Model } as *const CCModel))
+            .and_then(|model| model.animation_named(name))
+            .map(|animation| animation.name.clone())
+    });
+
+    if let Some(name) = &found {
+        animation::play(
+            entity_id,
+            unsafe { (*Entities.List[entity_id as usize]).Model },
+            name,
+        );
+    }
+    found.is_some()
+}
+
+/// Steps texture animations along.
+pub fn tick() {
+    MODELS.with(|cell| {
+        let models = &mut *cell.borrow_mut();
+
+        for model in models.values_mut() {
+            model.tick();
+        }
+    });
+
+    animation::stop_changed();
+}
+
+/// Unlinks `name` from the game, switching entities using it back to humanoid.
+pub fn unregister(name: &str) -> bool {
+    let ptr = MODELS.with(|cell| {
+        let models = &*cell.borrow();
+
+        models
+            .iter()
+            .find(|(_, model)| model.name == name)
+            .map(|(ptr, _)| *ptr)
+    });
+
+    match ptr {
+        Some(ptr) => {
+            unregister_ptr(ptr);
+            true
+        }
+        None => false,
+    }
+}
+
+fn unregister_ptr(ptr: *const CCModel) {
+    let name = MODELS.with(|cell| cell.borrow().get(&ptr).map(|model| model.name.clone()));
+    debug!("unregistering {:?}", name);
+
+    unsafe {
+        // nothing may point at it once it's dropped
+        reset_entities(ptr);
+        Model_Unregister(ptr as *mut CCModel);
+    }
+
+    MODELS.with(|cell| cell.borrow_mut().remove(&ptr));
+}
+
+unsafe fn reset_entities(ptr: *const CCModel) {
+    let humanoid = OwnedString::new("humanoid");
+
+    for id in 0..=255 {
+        let entity = Entities.List[id as usize];
+        if !entity.is_null() && ptr::eq((*entity).Model, ptr) {
+            Entity_SetModel(entity, &humanoid.as_cc_string());
+        }
+    }
+}
+
+pub fn init() {
+    unsafe {
+        Event_RegisterInt(
+            &mut *ptr::addr_of_mut!(EntityEvents.Added),
+            ptr::null_mut(),
+            Some(on_entity_added),
+        );
+        Event_RegisterInt(
+            &mut *ptr::addr_of_mut!(EntityEvents.Removed),
+            ptr::null_mut(),
+            Some(on_entity_removed),
+        );
+    }
+
+    tint::init();
+}
+
+extern "C" fn on_entity_added(_obj: *mut c_void, _id: c_int) {
+    tint::forget_entity_ids();
+}
+
+extern "C" fn on_entity_removed(_obj: *mut c_void, id: c_int) {
+    tint::forget_entity_ids();
+    tint::clear_tints(id as u8);
+    animation::stop(id as u8);
+}
+
+/// Reconnecting, per entity state belongs to the old server's entities.
+pub fn reset() {
+    tint::reset();
+    animation::reset();
+}
+
+/// Frees our textures, the game deletes everything on the gpu when the context is lost.
+pub fn context_lost() {
+    MODELS.with(|cell| {
+        let models = &mut *cell.borrow_mut();
+
+        for model in models.values_mut() {
+            model.frame_textures.clear();
+            unsafe {
+                model.default_tex.as_mut().get_unchecked_mut().texID = ptr::null_mut();
+            }
+        }
+    });
+}
+
+/// Recreates every model's textures and parts.
+pub fn rebuild() {
+    MODELS.with(|cell| {
+        let models = &mut *cell.borrow_mut();
+
+        for model in models.values_mut() {
+            model.create_textures();
+            model.make_parts();
+        }
+    });
+}
+
+pub fn free() {
+    debug!("model::free()");
+
+    let ptrs: Vec<*const CCModel> = MODELS.with(|cell| cell.borrow().keys().copied().collect());
+    for ptr in ptrs {
+        unregister_ptr(ptr);
+    }
+
+    unsafe {
+        Event_UnregisterInt(
+            &mut *ptr::addr_of_mut!(EntityEvents.Added),
+            ptr::null_mut(),
+            Some(on_entity_added),
+        );
+        Event_UnregisterInt(
+            &mut *ptr::addr_of_mut!(EntityEvents.Removed),
+            ptr::null_mut(),
+            Some(on_entity_removed),
+        );
+    }
+
+    tint::reset();
+    animation::reset();
+}
+
+#[allow(dead_code)]
+pub struct Model {
+    name: String,
+
+    model: Pin<Box<CCModel>>,
+
+    model_name: Pin<Box<CString>>,
+    vertices: Pin<Box<Vec<ModelVertex>>>,
+    /// like `vertices`, with animated parts moved while they're drawn
+    posed: Vec<ModelVertex>,
+    default_tex: Pin<Box<ModelTex>>,
+
+    default_tex_name: Pin<Box<CString>>,
+    /// BGRA with the frames stacked, kept to recreate `frame_textures`
+    pixels: Vec<u8>,
+    pixels_width: c_int,
+    /// one per animation frame
+    frame_textures: Vec<OwnedGfxTexture>,
+    texture_size: [c_float; 2],
+    animation: Option<TextureAnimation>,
+
+    parts: Vec<Part>,
+    attachments: Vec<AttachmentPoint>,
+    /// keyframe animations, not to be confused with `animation`
+    animations: Vec<Animation>,
+    options: ModelOptions,
+}
+
+impl Model {
+    pub fn register(
+        name: &str,
+        bmp: Bitmap,
+        animation: Option<TextureAnimation>,
+        parts: Vec<Part>,
+        attachments: Vec<AttachmentPoint>,
+        animations: Vec<Animation>,
+        options: ModelOptions,
+    ) {
+        if let Some(ptr) = MODELS.with(move |cell| {
+            let models = &mut *cell.borrow_mut();
+
+            for (ptr, model) in models.iter_mut() {
+                if model.name == name {
+                    return Some(*ptr);
+                }
+            }
+
+            None
+        }) {
+            Self::with_by_model_ptr(ptr, |model| {
+                model.update_existing(bmp, animation, parts, attachments, animations, options);
+            });
+            return;
+        }
+
+        debug!("registering {:?} with {} parts", name, parts.len());
+
+        let mut vertices = Box::pin(vec![unsafe { mem::zeroed() }; MAX_VERTICES]);
+
+        let frame_height = Self::frame_height(&bmp, &animation);
+        let texture_size = [bmp.Width as c_float, frame_height as c_float];
+        let pixels = Self::copy_pixels(&bmp);
+        let frame_textures = Self::create_frame_textures(bmp, frame_height);
+        let (mut default_tex, default_tex_name) =
+            Self::create_model_tex(&format!("{}_texture", name), frame_textures[0].resource_id);
+
+        // we don't need to register our texture!
+        // Model_RegisterTexture(default_tex.as_mut().get_unchecked_mut());
+
+        let (mut model, model_name) = Self::create_model(name, &mut vertices, &mut default_tex);
+
+        unsafe {
+            Model_Init(model.as_mut().get_unchecked_mut());
+            model.as_mut().get_unchecked_mut().usesSkin = options.uses_skin as _;
+
+            // TODO
+            // model.GetTransform = Some(Self::GetTransform);
+
+            Model_Register(model.as_mut().get_unchecked_mut());
+        }
+
+        let model = Self {
+            model,
+            name: name.to_string(),
+            model_name,
+            vertices,
+            posed: vec![unsafe { mem::zeroed() }; MAX_VERTICES],
+            default_tex,
+            default_tex_name,
+            pixels,
+            pixels_width: bmp.Width,
+            frame_textures,
+            texture_size,
+            animation,
+            parts,
+            attachments,
+            animations,
+            options,
+        };
+
+        MODELS.with(move |cell| {
+            let models = &mut *cell.borrow_mut();
+            models.insert(model.model.as_ref().get_ref(), model);
+        });
+    }
+
+    fn create_gfx_texture(mut bmp: Bitmap) -> OwnedGfxTexture {
+        OwnedGfxTexture::create(&mut bmp, true, false)
+    }
+
+    fn copy_pixels(bmp: &Bitmap) -> Vec<u8> {
+        let len = bmp.Width as usize * bmp.Height as usize * 4;
+        unsafe { slice::from_raw_parts(bmp.Scan0, len) }.to_vec()
+    }
+
+    /// Recreates `frame_textures` from `pixels`, showing the current frame.
+    fn create_textures(&mut self) {
+        let bmp = Bitmap {
+            Scan0: self.pixels.as_mut_ptr(),
+            Width: self.pixels_width,
+            Height: (self.pixels.len() / 4 / self.pixels_width.max(1) as usize) as c_int,
+        };
+        let frame_height = Self::frame_height(&bmp, &self.animation);
+        self.frame_textures = Self::create_frame_textures(bmp, frame_height);
+
+        let frame = self
+            .animation
+            .as_ref()
+            .map(|animation| animation.frame())
+            .unwrap_or(0);
+        unsafe {
+            self.default_tex.as_mut().get_unchecked_mut().texID =
+                self.frame_textures[frame].resource_id;
+        }
+    }
+
+    fn frame_height(bmp: &Bitmap, animation: &Option<TextureAnimation>) -> c_int {
+        animation
+            .as_ref()
+            .map(|animation| animation.frame_height as c_int)
+            .unwrap_or(bmp.Height)
+    }
+
+    /// Splits the stacked frames of `bmp` into their own textures.
+    fn create_frame_textures(bmp: Bitmap, frame_height: c_int) -> Vec<OwnedGfxTexture> {
+        let frame_pixels = bmp.Width as usize * frame_height as usize;
+
+        (0..(bmp.Height / frame_height) as usize)
+            .map(|frame| {
+                Self::create_gfx_texture(Bitmap {
+                    // 4 bytes per pixel
+                    Scan0: unsafe { bmp.Scan0.add(frame * frame_pixels * 4) },
+                    Width: bmp.Width,
+                    Height: frame_height,
+                })
+            })
+            .collect()
+    }
+
+    fn create_model_tex(
+        name: &str,
+        resource_id: GfxResourceID,
+    ) -> (Pin<Box<ModelTex>>, Pin<Box<CString>>) {
+        let mut tex: ModelTex = unsafe { mem::zeroed() };
+
+        let name = Box::pin(CString::new(name).unwrap());
+        tex.name = name.as_ptr();
+        tex.skinType = SKIN_TYPE_SKIN_64x64 as _;
+        tex.texID = resource_id;
+
+        (Box::pin(tex), name)
+    }
+
+    fn create_model(
+        name: &str,
+        vertices: &mut Pin<Box<Vec<ModelVertex>>>,
+        model_tex: &mut Pin<Box<ModelTex>>,
+    ) -> (Pin<Box<CCModel>>, Pin<Box<CString>>) {
+        let name = Box::pin(CString::new(name).unwrap());
+
+        let mut model: CCModel = unsafe { mem::zeroed() };
+        model.name = name.as_ptr();
+        model.vertices = vertices.as_mut_ptr();
+        model.defaultTex = unsafe { model_tex.as_mut().get_unchecked_mut() };
+        model.MakeParts = Some(Self::MakeParts);
+        model.Draw = Some(Self::Draw);
+        model.GetNameY = Some(Self::GetNameY);
+        model.GetEyeY = Some(Self::GetEyeY);
+        model.GetCollisionSize = Some(Self::GetCollisionSize);
+        model.GetPickingBounds = Some(Self::GetPickingBounds);
+
+        (Box::pin(model), name)
+    }
+
+    pub fn update_existing(
+        &mut self,
+        bmp: Bitmap,
+        animation: Option<TextureAnimation>,
+        parts: Vec<Part>,
+        attachments: Vec<AttachmentPoint>,
+        animations: Vec<Animation>,
+        options: ModelOptions,
+    ) {
+        debug!(
+            "updating existing {:?} with {} parts",
+            self.name,
+            parts.len()
+        );
+
+        let frame_height = Self::frame_height(&bmp, &animation);
+        self.texture_size = [bmp.Width as c_float, frame_height as c_float];
+        self.pixels = Self::copy_pixels(&bmp);
+        self.pixels_width = bmp.Width;
+        self.frame_textures = Self::create_frame_textures(bmp, frame_height);
+        self.animation = animation;
+
+        unsafe {
+            self.default_tex.as_mut().get_unchecked_mut().texID =
+                self.frame_textures[0].resource_id;
+        }
+
+        unsafe {
+            Model_Init(self.model.as_mut().get_unchecked_mut());
+            self.model.as_mut().get_unchecked_mut().usesSkin = options.uses_skin as _;
+        }
+
+        self.parts = parts;
+        self.attachments = attachments;
+        self.animations = animations;
+        self.options = options;
+
+        self.make_parts();
+    }
+
+    /// Like `MakeParts` but for when we're not the active model.
+    fn make_parts(&mut self) {
+        for vert in self.vertices.iter_mut() {
+            *vert = unsafe { mem::zeroed() };
+        }
+
+        unsafe {
+            let active = Models.Active;
+            Models.Active = self.model.as_mut().get_unchecked_mut();
+            {
+                debug!("rebuilding {} parts for {:?}", self.parts.len(), self.name);
+                part::make_parts(&mut self.parts);
+            }
+            self.model.initalised = 1;
+            self.model.index = 0;
+            Models.Active = active;
+        }
+    }
+
+    /// Where held blocks are drawn.
+    pub fn hand(&self) -> Option<&AttachmentPoint> {
+        self.attachments
+            .iter()
+            .find(|attachment| attachment.is_hand())
+    }
+
+    fn tick(&mut self) {
+        if let Some(animation) = &mut self.animation {
+            if animation.tick() {
+                let resource_id = self.frame_textures[animation.frame()].resource_id;
+                unsafe {
+                    self.default_tex.as_mut().get_unchecked_mut().texID = resource_id;
+                }
+            }
+        }
+    }
+
+    /// By its name or an alias from the sidecar's `animations`.
+    pub fn animation_named(&self, name: &str) -> Option<&Animation> {
+        let name = self
+            .options
+            .animations
+            .get(&name.to_lowercase())
+            .map(String::as_str)
+            .unwrap_or(name);
+
+        self.animations
+            .iter()
+            .find(|animation| animation.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn attachment(&self, name: &str) -> Option<&AttachmentPoint> {
+        self.attachments
+            .iter()
+            .find(|attachment| attachment.name.eq_ignore_ascii_case(name))
+    }
+
+    fn with_by_model_ptr<F, T>(ptr: *const CCModel, f: F) -> T
+    where
+        F: FnOnce(&mut Self) -> T,
+    {
+        MODELS.with(move |cell| {
+            let models = &mut *cell.borrow_mut();
+
+            f(models.get_mut(&ptr).unwrap())
+        })
+    }
+
+    /// Creates the ModelParts of this model and fills out vertices.
+    unsafe extern "C" fn MakeParts() {
+        let model = &mut *Models.Active;
+
+        Self::with_by_model_ptr(model, |model| {
+            let count = |f: fn(&Part) -> bool| model.parts.iter().filter(|part| f(part)).count();
+            debug!(
+                "building {} parts for {:?}, {} head, {} emissive, {} translucent",
+                model.parts.len(),
+                model.name,
+                count(Part::is_head),
+                count(Part::is_emissive),
+                count(Part::is_translucent)
+            );
+            part::make_parts(&mut model.parts);
+        });
+    }
+
+    /// Draws either the opaque or the translucent parts.
+    unsafe fn draw_parts(&mut self, entity: &Entity, entity_id: Option<u8>, translucent: bool) {
+        let animations = &self.animations;
+        let pose = entity_id
+            .and_then(animation::playing)
+            .and_then(|(name, seconds)| {
+                animations
+                    .iter()
+                    .find(|animation| animation.name.eq_ignore_ascii_case(&name))?
+                    .pose(seconds)
+            })
+            .unwrap_or_default();
+
+        let vertices = &self.vertices;
+        let posed = &mut self.posed;
+        let model = self.model.as_mut().get_unchecked_mut();
+        let unposed = model.vertices;
+        for part in &mut self.parts {
+            if part.is_translucent() != translucent {
+                continue;
+            }
+
+            // innermost group first
+            let transforms: Vec<&BoneTransform> = part
+                .group_uuids()
+                .iter()
+                .rev()
+                .filter_map(|uuid| pose.get(uuid.as_str()))
+                .collect();
+
+            // the game draws from the model's vertices, point it at a posed copy just for this
+            model.vertices = match part.vertex_range() {
+                Some(range) if !transforms.is_empty() => {
+                    for (posed, vertex) in posed[range.clone()].iter_mut().zip(&vertices[range]) {
+                        let mut position = [vertex.X, vertex.Y, vertex.Z];
+                        for transform in &transforms {
+                            position = transform.apply(position);
+                        }
+                        let [x, y, z] = position;
+                        *posed = ModelVertex {
+                            X: x,
+                            Y: y,
+                            Z: z,
+                            ..*vertex
+                        };
+                    }
+                    posed.as_mut_ptr()
+                }
+                _ => unposed,
+            };
+
+            let tint = entity_id.and_then(|id| tint::tint(id, part.groups()));
+            part.draw(entity, tint);
+        }
+        model.vertices = unposed;
+    }
+
+    /// Draws/Renders this model for the given entity.
+    unsafe extern "C" fn Draw(entity: *mut Entity) {
+        let entity = &mut *entity;
+
+        Model_ApplyTexture(entity);
+
+        let entity_id = tint::entity_id(entity);
+
+        let hand = Self::with_by_model_ptr(entity.Model, |model| {
+            // our uvs are in `UV_PRECISION`ths of a pixel of our own texture
+            Models.uScale = 1.0 / (model.texture_size[0] * UV_PRECISION);
+            Models.vScale = 1.0 / (model.texture_size[1] * UV_PRECISION);
+
+            model.draw_parts(entity, entity_id, false);
+
+            if model.parts.iter().any(|part| part.is_translucent()) {
+                // blend translucent parts over everything opaque already drawn
+                Model_UpdateVB();
+                Gfx_SetAlphaBlending(1);
+                Gfx_SetDepthWrite(0);
+
+                model.draw_parts(entity, entity_id, true);
+                Model_UpdateVB();
+
+                Gfx_SetDepthWrite(1);
+                Gfx_SetAlphaBlending(0);
+            }
+
+            model.hand().map(|hand| hand.transform(entity))
+        });
+
+        Model_UpdateVB();
+
+        // draws with the block model, which needs our borrow of MODELS gone
+        if let (Some(hand), Some(block)) = (hand, held_block::held_block(entity)) {
+            held_block::draw_held_block(entity, block, hand);
+        }
+    }
+
+    /// Returns height the 'nametag' gets drawn at above the entity's feet.
+    unsafe extern "C" fn GetNameY(entity: *mut Entity) -> c_float {
+        let entity = &mut *entity;
+
+        Self::with_by_model_ptr(entity.Model, |model| {
+            model
+                .options
+                .name_y
+                .or_else(|| {
+                    model
+                        .attachment("nametag")
+                        .map(|attachment| attachment.position[1])
+                })
+                .unwrap_or_else(|| model.options.apply([0.0, 32.5 / 16.0, 0.0])[1])
+        })
+    }
+
+    /// Returns height the 'eye' is located at above the entity's feet.
+    unsafe extern "C" fn GetEyeY(entity: *mut Entity) -> c_float {
+        let entity = &mut *entity;
+
+        Self::with_by_model_ptr(entity.Model, |model| {
+            model
+                .options
+                .eye_y
+                .or_else(|| {
+                    model
+                        .attachment("eye")
+                        .map(|attachment| attachment.position[1])
+                })
+                .unwrap_or_else(|| model.options.apply([0.0, 26.0 / 16.0, 0.0])[1])
+        })
+    }
+
+    /// Sets entity->Size to the collision size of this model.
+    unsafe extern "C" fn GetCollisionSize(entity: *mut Entity) {
+        let entity = &mut *entity;
+
+        let [x, y, z] = Self::with_by_model_ptr(entity.Model, |model| {
+            let scale = model.options.scale;
+            model
+                .options
+                .collision_size
+                .unwrap_or([8.6 * scale, 28.1 * scale, 8.6 * scale])
+        });
+        Model_RetSize!(entity, x, y, z);
+    }
+
+    /// Sets entity->ModelAABB to the 'picking' bounds of this model.
+    /// This is the AABB around the entity in which mouse clicks trigger 'interaction'.
+    /// NOTE: These bounds are not transformed. (i.e. no rotation, centered around 0,0,0)
+    unsafe extern "C" fn GetPickingBounds(entity: *mut Entity) {
+        let entity = &mut *entity;
+
+        let options = Self::with_by_model_ptr(entity.Model, |model| model.options.clone());
+        let [x1, y1, z1] = options.apply([-8.0 / 16.0, 0.0, -4.0 / 16.0]);
+        let [x2, y2, z2] = options.apply([8.0 / 16.0, 32.0 / 16.0, 4.0 / 16.0]);
+        // takes pixels
+        Model_RetAABB!(
+            entity,
+            x1 * 16.0,
+            y1 * 16.0,
+            z1 * 16.0,
+            x2 * 16.0,
+            y2 * 16.0,
+            z2 * 16.0
+        );
+    }
+
+    // unsafe extern "C" fn GetTransform(entity: *mut Entity, pos: Vec3, m: *mut Matrix) {
+    //     //
+    // }
+}
@@ -0,0 +1,198 @@
+use std::{ops::Mul, os::raw::c_float};
+
+/// An element's rotation, composed the way Blockbench does it:
+/// degrees, right-handed, applied around X first, then Y, then Z
+/// (three.js' "ZYX" euler order).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rotation {
+    matrix: [[c_float; 3]; 3],
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: [c_float; 3]) -> Self {
+        let [x, y, z] = degrees;
+        let (sx, cx) = x.to_radians().sin_cos();
+        let (sy, cy) = y.to_radians().sin_cos();
+        let (sz, cz) = z.to_radians().sin_cos();
+
+        // Rz * Ry * Rx
+        let matrix = [
+            [cy * cz, sx * sy * cz - cx * sz, cx * sy * cz + sx * sz],
+            [cy * sz, sx * sy * sz + cx * cz, cx * sy * sz - sx * cz],
+            [-sy, sx * cy, cx * cy],
+        ];
+
+        Self { matrix }
+    }
+
+    /// Java block models' `rescale`: the two axes perpendicular to the rotation
+    /// axis are stretched by `1 / cos(angle)` so the element keeps spanning
+    /// the whole block.
+    ///
+    /// Only the first non-zero axis is considered, like Blockbench.
+    pub fn from_degrees_rescaled(degrees: [c_float; 3]) -> Self {
+        let mut rotation = Self::from_degrees(degrees);
+
+        if let Some(axis) = degrees.iter().position(|angle| *angle != 0.0) {
+            let scale = 1.0 / degrees[axis].to_radians().cos().abs();
+
+            for row in &mut rotation.matrix {
+                for (column, value) in row.iter_mut().enumerate() {
+                    if column != axis {
+                        *value *= scale;
+                    }
+                }
+            }
+        }
+
+        rotation
+    }
+
+    /// row major
+    pub fn matrix(&self) -> [[c_float; 3]; 3] {
+        self.matrix
+    }
+
+    /// Angles `[x, y, z]` in degrees such that rotating around Z, then X, then Y
+    /// gives this rotation, the order entities are rotated in.
+    pub fn to_yxz_degrees(self) -> [c_float; 3] {
+        let m = &self.matrix;
+
+        let x = (-m[1][2]).clamp(-1.0, 1.0).asin();
+        let y = m[0][2].atan2(m[2][2]);
+        let z = m[1][0].atan2(m[1][1]);
+
+        [x.to_degrees(), y.to_degrees(), z.to_degrees()]
+    }
+
+    pub fn apply(&self, v: [c_float; 3]) -> [c_float; 3] {
+        let m = &self.matrix;
+
+        [
+            m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+            m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+            m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+        ]
+    }
+
+    pub fn apply_around(&self, v: [c_float; 3], pivot: [c_float; 3]) -> [c_float; 3] {
+        let local = [v[0] - pivot[0], v[1] - pivot[1], v[2] - pivot[2]];
+        let rotated = self.apply(local);
+
+        [
+            rotated[0] + pivot[0],
+            rotated[1] + pivot[1],
+            rotated[2] + pivot[2],
+        ]
+    }
+}
+
+impl Mul for Rotation {
+    type Output = Self;
+
+    /// `a * b` rotates by `b` first, then by `a`
+    fn mul(self, rhs: Self) -> Self {
+        let mut matrix = [[0.0; 3]; 3];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.matrix[i][k] * rhs.matrix[k][j]).sum();
+            }
+        }
+
+        Self { matrix }
+    }
+}
+
+#[cfg(test)]
+fn assert_close(a: [c_float; 3], b: [c_float; 3]) {
+    for i in 0..3 {
+        assert!((a[i] - b[i]).abs() < 0.0001, "{:?} != {:?}", a, b);
+    }
+}
+
+#[test]
+fn test_rotation_single_axis() {
+    // right-handed: a quarter turn takes each axis onto the next one
+    assert_close(
+        Rotation::from_degrees([90.0, 0.0, 0.0]).apply([0.0, 1.0, 0.0]),
+        [0.0, 0.0, 1.0],
+    );
+    assert_close(
+        Rotation::from_degrees([0.0, 90.0, 0.0]).apply([0.0, 0.0, 1.0]),
+        [1.0, 0.0, 0.0],
+    );
+    assert_close(
+        Rotation::from_degrees([0.0, 0.0, 90.0]).apply([1.0, 0.0, 0.0]),
+        [0.0, 1.0, 0.0],
+    );
+}
+
+#[test]
+fn test_rotation_multi_axis() {
+    // X by 30, then Y by 45, then Z by 60, each done separately with Rodrigues' formula
+    assert_close(
+        Rotation::from_degrees([30.0, 45.0, 60.0]).apply([1.0, 2.0, 3.0]),
+        [1.424_703_5, 2.931_760_5, 1.837_117_3],
+    );
+
+    let composed = Rotation::from_degrees([0.0, 0.0, 60.0])
+        * Rotation::from_degrees([0.0, 45.0, 0.0])
+        * Rotation::from_degrees([30.0, 0.0, 0.0]);
+    assert_close(
+        composed.apply([1.0, 2.0, 3.0]),
+        [1.424_703_5, 2.931_760_5, 1.837_117_3],
+    );
+
+    // composing in the wrong order gives something else
+    let yxz = Rotation::from_degrees([0.0, 45.0, 0.0]).apply(
+        Rotation::from_degrees([30.0, 0.0, 0.0])
+            .apply(Rotation::from_degrees([0.0, 0.0, 60.0]).apply([1.0, 2.0, 3.0])),
+    );
+    assert!((yxz[0] - 1.424_703_5).abs() > 0.01);
+}
+
+#[test]
+fn test_rotation_pivot() {
+    assert_close(
+        Rotation::from_degrees([0.0, 55.0, 0.0])
+            .apply_around([-7.0, 10.75, -1.0], [-3.5, 21.75, 1.0]),
+        [-7.145_821_6, 10.75, 2.719_879_3],
+    );
+}
+
+#[test]
+fn test_rotation_rescale() {
+    let rotation = Rotation::from_degrees_rescaled([0.0, 0.0, 45.0]);
+
+    // the corners of a full block stay on the block's edges
+    assert_close(rotation.apply([0.5, 0.5, 0.5]), [0.0, 1.0, 0.5]);
+    assert_close(rotation.apply([0.5, -0.5, -0.5]), [1.0, 0.0, -0.5]);
+
+    // the rotation axis itself is left alone
+    let rotation = Rotation::from_degrees_rescaled([-22.5, 0.0, 0.0]);
+    assert_close(
+        rotation.apply([1.0, 1.0, 0.0]),
+        [1.0, 1.0, -22.5f32.to_radians().tan()],
+    );
+}
+
+#[test]
+fn test_rotation_yxz_degrees() {
+    let yxz = |[x, y, z]: [c_float; 3]| {
+        Rotation::from_degrees([0.0, y, 0.0])
+            * Rotation::from_degrees([x, 0.0, 0.0])
+            * Rotation::from_degrees([0.0, 0.0, z])
+    };
+
+    for angles in &[[0.0, 90.0, 0.0], [10.0, -20.0, 30.0], [-45.0, 170.0, -80.0]] {
+        assert_close(yxz(*angles).to_yxz_degrees(), *angles);
+    }
+
+    // a blockbench rotation as the angles entities want
+    let rotation = Rotation::from_degrees([30.0, 45.0, 60.0]);
+    let angles = rotation.to_yxz_degrees();
+    assert_close(
+        yxz(angles).apply([1.0, 2.0, 3.0]),
+        rotation.apply([1.0, 2.0, 3.0]),
+    );
+}
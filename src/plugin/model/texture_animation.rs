@@ -0,0 +1,59 @@
+/// A texture made of vertically stacked frames, cycled through on tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureAnimation {
+    /// in pixels, the texture's height is a multiple of this
+    pub frame_height: usize,
+
+    /// ticks each frame is shown for
+    pub frame_time: u32,
+
+    /// frame numbers in the order they're shown
+    pub order: Vec<usize>,
+
+    ticks: u32,
+}
+
+impl TextureAnimation {
+    pub fn new(frame_height: usize, frame_time: u32, order: Vec<usize>) -> Self {
+        Self {
+            frame_height,
+            frame_time: frame_time.max(1),
+            order,
+            ticks: 0,
+        }
+    }
+
+    /// The frame to show right now.
+    pub fn frame(&self) -> usize {
+        if self.order.is_empty() {
+            return 0;
+        }
+
+        let step = (self.ticks / self.frame_time) as usize % self.order.len();
+        self.order[step]
+    }
+
+    /// Returns true if the frame changed.
+    pub fn tick(&mut self) -> bool {
+        let before = self.frame();
+        self.ticks = self.ticks.wrapping_add(1);
+        self.frame() != before
+    }
+}
+
+#[test]
+fn test_texture_animation() {
+    let mut animation = TextureAnimation::new(16, 2, vec![0, 1, 2, 1]);
+
+    let mut frames = vec![animation.frame()];
+    let mut changes = 0;
+    for _ in 0..9 {
+        if animation.tick() {
+            changes += 1;
+        }
+        frames.push(animation.frame());
+    }
+
+    assert_eq!(frames, vec![0, 0, 1, 1, 2, 2, 1, 1, 0, 0]);
+    assert_eq!(changes, 4);
+}
@@ -0,0 +1,255 @@
+//! Per entity colors, set through the api or `/client tint`.
+
+use crate::error::*;
+use classicube_sys::{
+    ChatCommand, Chat_Add, Commands_Register, Entities, Entity, OwnedString, PackedCol,
+    PackedCol_Make, ENTITIES_SELF_ID,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    os::raw::{c_char, c_int},
+    ptr, slice,
+};
+
+/// Group name, or `None` for the whole model.
+type Tints = HashMap<Option<String>, PackedCol>;
+
+thread_local!(
+    static TINTS: RefCell<HashMap<u8, Tints>> = Default::default();
+);
+
+thread_local!(
+    /// entity pointers to their ids, so drawing doesn't search every slot,
+    /// emptied whenever an entity comes or goes
+    static ENTITY_IDS: RefCell<HashMap<*const Entity, Option<u8>>> = Default::default();
+);
+
+const COMMAND_NAME: &[u8] = b"Tint\0";
+const COMMAND_HELP: [&[u8]; 3] = [
+    b"&a/client tint [entity id] [rrggbb] <group>\0",
+    b"&eTints the entity's Blockbench model, or just one group of it.\0",
+    b"&a/client tint [entity id] clear &eremoves its tints, \"self\" is you.\0",
+];
+
+/// Registers `/client tint`.
+pub fn init() {
+    unsafe {
+        // the game keeps it until it closes
+        let command: &mut ChatCommand = Box::leak(Box::new(mem::zeroed()));
+        command.name = COMMAND_NAME.as_ptr() as *const c_char;
+        command.Execute = Some(on_command);
+        for (line, help) in command.help.iter_mut().zip(&COMMAND_HELP) {
+            *line = help.as_ptr() as *const c_char;
+        }
+        Commands_Register(command);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Set {
+        entity_id: u8,
+        group: Option<String>,
+        rgb: [u8; 3],
+    },
+    Clear {
+        entity_id: u8,
+    },
+}
+
+impl Command {
+    fn parse(args: &[String]) -> Result<Self> {
+        let (entity_id, rest) = args.split_first().chain_err(|| "no entity id")?;
+        let entity_id = if entity_id.eq_ignore_ascii_case("self") {
+            ENTITIES_SELF_ID as u8
+        } else {
+            entity_id.parse().chain_err(|| "bad entity id")?
+        };
+
+        match rest {
+            [clear] if clear.eq_ignore_ascii_case("clear") => Ok(Command::Clear { entity_id }),
+
+            [color] | [color, _] => {
+                let color = color.trim_start_matches('#');
+                ensure!(color.len() == 6, "color should be like ff8000");
+                let mut rgb = [0; 3];
+                for (i, channel) in rgb.iter_mut().enumerate() {
+                    *channel = u8::from_str_radix(&color[i * 2..i * 2 + 2], 16)
+                        .chain_err(|| "color should be like ff8000")?;
+                }
+
+                Ok(Command::Set {
+                    entity_id,
+                    group: rest.get(1).cloned(),
+                    rgb,
+                })
+            }
+
+            _ => bail!("expected a color or \"clear\""),
+        }
+    }
+
+    fn run(self) {
+        match self {
+            Command::Set {
+                entity_id,
+                group,
+                rgb,
+            } => set_tint(entity_id, group.as_deref(), rgb),
+            Command::Clear { entity_id } => clear_tints(entity_id),
+        }
+    }
+}
+
+unsafe extern "C" fn on_command(args: *const classicube_sys::String, args_count: c_int) {
+    let args: Vec<String> = slice::from_raw_parts(args, args_count as usize)
+        .iter()
+        .map(|arg| {
+            let bytes = slice::from_raw_parts(arg.buffer as *const u8, arg.length as usize);
+            String::from_utf8_lossy(bytes).to_string()
+        })
+        .collect();
+
+    match Command::parse(&args) {
+        Ok(command) => command.run(),
+        Err(e) => {
+            let text = OwnedString::new(format!("&ctint: {}", e));
+            Chat_Add(&text.as_cc_string());
+        }
+    }
+}
+
+/// Tints `group` of `entity_id`'s model, or the whole model.
+pub fn set_tint(entity_id: u8, group: Option<&str>, rgb: [u8; 3]) {
+    let [r, g, b] = rgb;
+
+    TINTS.with(|cell| {
+        let tints = &mut *cell.borrow_mut();
+        tints
+            .entry(entity_id)
+            .or_default()
+            .insert(group.map(str::to_lowercase), PackedCol_Make(r, g, b, 255));
+    });
+}
+
+pub fn clear_tints(entity_id: u8) {
+    TINTS.with(|cell| {
+        let tints = &mut *cell.borrow_mut();
+        tints.remove(&entity_id);
+    });
+}
+
+/// Reconnecting, entity ids mean other entities now.
+pub fn reset() {
+    TINTS.with(|cell| {
+        let tints = &mut *cell.borrow_mut();
+        tints.clear();
+    });
+    forget_entity_ids();
+}
+
+/// An entity came or went, ids may belong to other entities now.
+pub fn forget_entity_ids() {
+    ENTITY_IDS.with(|cell| cell.borrow_mut().clear());
+}
+
+/// `None` for entities the game doesn't list, like ones drawn in menus.
+pub fn entity_id(entity: &Entity) -> Option<u8> {
+    let key = entity as *const Entity;
+    if let Some(id) = ENTITY_IDS.with(|cell| cell.borrow().get(&key).copied()) {
+        return id;
+    }
+
+    let id = (0..=255).find(|id| unsafe { ptr::eq(Entities.List[*id as usize], entity) });
+    ENTITY_IDS.with(|cell| cell.borrow_mut().insert(key, id));
+    id
+}
+
+/// The tint of the innermost of `groups` that has one, else the whole model's.
+pub fn tint(entity_id: u8, groups: &[String]) -> Option<PackedCol> {
+    TINTS.with(|cell| {
+        let tints = &*cell.borrow();
+        let tints = tints.get(&entity_id)?;
+
+        groups
+            .iter()
+            .rev()
+            .find_map(|group| tints.get(&Some(group.to_lowercase())))
+            .or_else(|| tints.get(&None))
+            .copied()
+    })
+}
+
+/// Multiplies each channel, so white becomes the tint and black stays black.
+pub fn tint_col(col: PackedCol, tint: PackedCol) -> PackedCol {
+    let mut tinted = 0;
+    for shift in (0..32).step_by(8) {
+        let a = (col >> shift) & 0xFF;
+        let b = (tint >> shift) & 0xFF;
+        tinted |= (a * b / 255) << shift;
+    }
+
+    tinted
+}
+
+#[test]
+fn test_tint_col() {
+    assert_eq!(tint_col(0xFFFF_FFFF, 0xFF10_2030), 0xFF10_2030);
+    assert_eq!(tint_col(0xFF80_8080, 0xFFFF_0000), 0xFF80_0000);
+    assert_eq!(tint_col(0x0000_0000, 0xFFFF_FFFF), 0x0000_0000);
+}
+
+#[test]
+fn test_tint_groups() {
+    let white = PackedCol_Make(255, 255, 255, 255);
+    let red = PackedCol_Make(255, 0, 0, 255);
+
+    set_tint(1, None, [255, 255, 255]);
+    set_tint(1, Some("Eyes"), [255, 0, 0]);
+
+    let groups = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(tint(1, &groups(&["body"])), Some(white));
+    assert_eq!(tint(1, &groups(&["body", "eyes"])), Some(red));
+    assert_eq!(tint(2, &groups(&["eyes"])), None);
+
+    clear_tints(1);
+    assert_eq!(tint(1, &groups(&["eyes"])), None);
+}
+
+#[test]
+fn test_command() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(
+        Command::parse(&args(&["3", "#ff8000"])).unwrap(),
+        Command::Set {
+            entity_id: 3,
+            group: None,
+            rgb: [255, 128, 0],
+        }
+    );
+    assert_eq!(
+        Command::parse(&args(&["self", "00ff00", "Eyes"])).unwrap(),
+        Command::Set {
+            entity_id: ENTITIES_SELF_ID as u8,
+            group: Some("Eyes".to_string()),
+            rgb: [0, 255, 0],
+        }
+    );
+    assert_eq!(
+        Command::parse(&args(&["3", "Clear"])).unwrap(),
+        Command::Clear { entity_id: 3 }
+    );
+
+    assert!(Command::parse(&args(&[])).is_err());
+    assert!(Command::parse(&args(&["300", "ff8000"])).is_err());
+    assert!(Command::parse(&args(&["3", "red"])).is_err());
+    assert!(Command::parse(&args(&["3"])).is_err());
+}
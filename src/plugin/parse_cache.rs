@@ -0,0 +1,248 @@
+//! Parsed models by the sha256 of their file, so files that didn't change aren't
+//! parsed or registered again. Parsed models can also be kept on disk in a compact
+//! binary form so startup doesn't read json or decode every png.
+//!
+//! Used from the worker thread.
+
+use super::{
+    blockbench::Blockbench,
+    cache::{self, Hash, CACHE_PATH},
+    sidecar::Sidecar,
+};
+use crate::error::*;
+use log::*;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8; 4] = b"BBPM";
+const VERSION: u8 = 2;
+
+/// the oldest parsed models are deleted past this
+const MAX_DISK_SIZE: u64 = 64 * 1024 * 1024;
+
+thread_local!(
+    /// parsed models of the files registered right now
+    static PARSED: RefCell<HashMap<Hash, Blockbench>> = Default::default();
+);
+
+thread_local!(
+    /// what each model was last registered from
+    static REGISTERED: RefCell<HashMap<String, (Hash, Option<Sidecar>)>> = Default::default();
+);
+
+/// If `name` was already registered from exactly this.
+pub fn is_unchanged(name: &str, hash: &Hash, sidecar: Option<&Sidecar>) -> bool {
+    REGISTERED.with(|cell| {
+        cell.borrow()
+            .get(name)
+            .map(|(registered, registered_sidecar)| {
+                registered == hash && registered_sidecar.as_ref() == sidecar
+            })
+            .unwrap_or(false)
+    })
+}
+
+/// `data` is the file `hash` was made from.
+pub fn parse(hash: &Hash, data: &[u8], disk_cache: bool) -> Result<Blockbench> {
+    if let Some(bb) = PARSED.with(|cell| cell.borrow().get(hash).cloned()) {
+        return Ok(bb);
+    }
+
+    let dir = Path::new(CACHE_PATH).join("parsed");
+
+    let cached = if disk_cache {
+        read_parsed(&dir, hash)
+            .map_err(|e| debug!("no parsed {}: {}", cache::to_hex(hash), e))
+            .ok()
+    } else {
+        None
+    };
+
+    let bb = match cached {
+        Some(bb) => bb,
+        None => {
+            let bb = Blockbench::parse_bbmodel(data)?;
+            if disk_cache {
+                if let Err(e) = write_parsed(&dir, hash, &bb) {
+                    warn!("couldn't cache {}: {}", cache::to_hex(hash), e);
+                }
+                if let Err(e) = prune(&dir, MAX_DISK_SIZE, hash) {
+                    warn!("couldn't prune {:?}: {}", dir, e);
+                }
+            }
+            bb
+        }
+    };
+
+    PARSED.with(|cell| cell.borrow_mut().insert(*hash, bb.clone()));
+    Ok(bb)
+}
+
+/// Remembers what `name` was registered from, letting go of what it used before.
+pub fn registered(name: &str, hash: Hash, sidecar: Option<Sidecar>) {
+    let old = REGISTERED.with(|cell| {
+        cell.borrow_mut()
+            .insert(name.to_string(), (hash, sidecar))
+            .map(|(old, _)| old)
+    });
+
+    if let Some(old) = old {
+        forget_unused(&old);
+    }
+}
+
+pub fn forget(name: &str) {
+    let old = REGISTERED.with(|cell| cell.borrow_mut().remove(name));
+
+    if let Some((old, _)) = old {
+        forget_unused(&old);
+    }
+}
+
+fn forget_unused(hash: &Hash) {
+    let used = REGISTERED.with(|cell| cell.borrow().values().any(|(used, _)| used == hash));
+    if !used {
+        PARSED.with(|cell| cell.borrow_mut().remove(hash));
+    }
+}
+
+pub fn free() {
+    PARSED.with(|cell| cell.borrow_mut().clear());
+    REGISTERED.with(|cell| cell.borrow_mut().clear());
+}
+
+fn parsed_path(dir: &Path, hash: &Hash) -> PathBuf {
+    dir.join(format!("{}.bin", cache::to_hex(hash)))
+}
+
+/// `MAGIC`, `VERSION`, then `Blockbench::to_binary`.
+fn write_parsed(dir: &Path, hash: &Hash, bb: &Blockbench) -> Result<()> {
+    let binary = bb.to_binary();
+    let mut data = Vec::with_capacity(MAGIC.len() + 1 + binary.len());
+    data.extend_from_slice(MAGIC);
+    data.push(VERSION);
+    data.extend_from_slice(&binary);
+
+    fs::create_dir_all(dir)?;
+    fs::write(parsed_path(dir, hash), data)?;
+
+    Ok(())
+}
+
+/// Deletes the oldest files in `dir` until they fit in `max_size` bytes,
+/// never the one for `keep`.
+fn prune(dir: &Path, max_size: u64, keep: &Hash) -> Result<()> {
+    let keep = parsed_path(dir, keep);
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            files.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+    }
+
+    let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort();
+    for (_, len, path) in files {
+        if size <= max_size {
+            break;
+        }
+        if path != keep {
+            fs::remove_file(&path)?;
+            size -= len;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_parsed(dir: &Path, hash: &Hash) -> Result<Blockbench> {
+    let data = fs::read(parsed_path(dir, hash))?;
+
+    ensure!(
+        data.len() > MAGIC.len() && data.starts_with(MAGIC),
+        "not a parsed model"
+    );
+    ensure!(
+        data[MAGIC.len()] == VERSION,
+        "parsed model version {}",
+        data[MAGIC.len()]
+    );
+
+    Blockbench::from_binary(&data[MAGIC.len() + 1..])
+}
+
+#[test]
+fn test_disk_cache() {
+    let dir = std::env::temp_dir().join("blockbench-loader-test-parse-cache");
+    let _ = fs::remove_dir_all(&dir);
+
+    let data = fs::read("tests/animated.bbmodel").unwrap();
+    let hash = cache::hash(&data);
+    let parsed = Blockbench::parse_bbmodel(&data).unwrap();
+
+    assert!(read_parsed(&dir, &hash).is_err());
+    write_parsed(&dir, &hash, &parsed).unwrap();
+    let cached = read_parsed(&dir, &hash).unwrap();
+    assert_eq!(cached.pixels(), parsed.pixels());
+    assert_eq!(cached.options(), parsed.options());
+
+    let path = parsed_path(&dir, &hash);
+    let mut truncated = fs::read(&path).unwrap();
+    truncated.pop();
+    fs::write(&path, truncated).unwrap();
+    assert!(read_parsed(&dir, &hash).is_err());
+
+    // only the newest fits
+    let other = cache::hash(b"other");
+    write_parsed(&dir, &other, &parsed).unwrap();
+    let size = fs::metadata(parsed_path(&dir, &other)).unwrap().len();
+    prune(&dir, size, &other).unwrap();
+    assert!(!parsed_path(&dir, &hash).exists());
+    assert!(read_parsed(&dir, &other).is_ok());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_memory_cache() {
+    let data = fs::read("tests/animated.bbmodel").unwrap();
+    let hash = cache::hash(&data);
+
+    parse(&hash, &data, false).unwrap();
+    registered("npc", hash, None);
+    registered("other_npc", hash, None);
+
+    // the file isn't read again
+    assert!(parse(&hash, b"not json", false).is_ok());
+
+    forget("npc");
+    assert!(parse(&hash, b"not json", false).is_ok());
+
+    forget("other_npc");
+    assert!(parse(&hash, b"not json", false).is_err());
+}
+
+#[test]
+fn test_unchanged() {
+    let hash = cache::hash(b"model");
+    let sidecar = Sidecar {
+        scale: Some(2.0),
+        ..Default::default()
+    };
+
+    assert!(!is_unchanged("npc", &hash, None));
+    registered("npc", hash, None);
+    assert!(is_unchanged("npc", &hash, None));
+    assert!(!is_unchanged("npc", &hash, Some(&sidecar)));
+    assert!(!is_unchanged("npc", &cache::hash(b"other"), None));
+
+    free();
+    assert!(!is_unchanged("npc", &hash, None));
+}
@@ -0,0 +1,359 @@
+//! Models pushed by the server over a CPE PluginMessage channel.
+//!
+//! Messages are 64 bytes, starting with their kind:
+//! - `USE`: 32 byte sha256 then the name, loaded from the cache or answered with `MISSING`
+//! - `MISSING` (to the server): the sha256 of a model the client doesn't have
+//! - `START`: big endian u32 length then the name, starts a transfer
+//! - `DATA`: the next 63 bytes of the model or list
+//! - `URLS`: like `START` but for a list of `name url sha256` lines to download
+//!
+//! Names and the last `DATA` are zero padded.
+
+use super::{
+    cache::{self, Cache, Hash},
+    downloads::{self, Download},
+    files,
+    loader::{report_error, unregister},
+    model,
+    worker::{self, Job},
+};
+use crate::error::*;
+use classicube_sys::{
+    CPE_SendPluginMessage, Event_RegisterPluginMessage, Event_UnregisterPluginMessage, Model_Get,
+    NetEvents, OwnedString,
+};
+use log::*;
+use std::{cell::RefCell, os::raw::c_void, path::Path, ptr};
+
+pub const CHANNEL: u8 = 66;
+
+pub const MESSAGE_SIZE: usize = 64;
+
+const USE: u8 = 0;
+const MISSING: u8 = 1;
+const START: u8 = 2;
+const DATA: u8 = 3;
+const URLS: u8 = 4;
+
+/// bigger than any sane model, but stops a server from eating all our memory
+pub const MAX_LENGTH: usize = 16 * 1024 * 1024;
+
+thread_local!(
+    static RECEIVER: RefCell<Receiver> = Default::default();
+);
+
+thread_local!(
+    /// names of the models the server gave us
+    static LOADED: RefCell<Vec<String>> = Default::default();
+);
+
+#[derive(Debug, PartialEq)]
+pub enum Received {
+    Use { hash: Hash, name: String },
+    Model { name: String, data: Vec<u8> },
+    Downloads(Vec<Download>),
+}
+
+struct Transfer {
+    /// `None` for a `URLS` list
+    name: Option<String>,
+    length: usize,
+    data: Vec<u8>,
+}
+
+/// Puts models back together from their messages.
+#[derive(Default)]
+pub struct Receiver {
+    transfer: Option<Transfer>,
+}
+
+impl Receiver {
+    pub fn receive(&mut self, message: &[u8; MESSAGE_SIZE]) -> Result<Option<Received>> {
+        match message[0] {
+            USE => {
+                let mut hash = [0; 32];
+                hash.copy_from_slice(&message[1..33]);
+                let name = read_name(&message[33..])?;
+
+                Ok(Some(Received::Use { hash, name }))
+            }
+
+            START | URLS => {
+                let length =
+                    u32::from_be_bytes([message[1], message[2], message[3], message[4]]) as usize;
+                ensure!(
+                    length <= MAX_LENGTH,
+                    "transfer is too big ({} bytes)",
+                    length
+                );
+                let name = if message[0] == START {
+                    Some(read_name(&message[5..])?)
+                } else {
+                    None
+                };
+
+                self.transfer = Some(Transfer {
+                    name,
+                    length,
+                    data: Vec::with_capacity(length),
+                });
+
+                self.finish()
+            }
+
+            DATA => {
+                let transfer = self
+                    .transfer
+                    .as_mut()
+                    .chain_err(|| "data without a start")?;
+
+                let remaining = transfer.length - transfer.data.len();
+                let chunk = &message[1..];
+                transfer
+                    .data
+                    .extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+
+                self.finish()
+            }
+
+            kind => bail!("unknown message kind {}", kind),
+        }
+    }
+
+    fn finish(&mut self) -> Result<Option<Received>> {
+        let done = self
+            .transfer
+            .as_ref()
+            .map(|transfer| transfer.data.len() == transfer.length)
+            .unwrap_or(false);
+        if !done {
+            return Ok(None);
+        }
+
+        match self.transfer.take() {
+            Some(Transfer {
+                name: Some(name),
+                data,
+                ..
+            }) => Ok(Some(Received::Model { name, data })),
+
+            Some(Transfer {
+                name: None, data, ..
+            }) => {
+                let text = String::from_utf8(data).chain_err(|| "url list isn't utf-8")?;
+                Ok(Some(Received::Downloads(Download::parse_list(&text)?)))
+            }
+
+            None => Ok(None),
+        }
+    }
+}
+
+fn read_name(bytes: &[u8]) -> Result<String> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let name = std::str::from_utf8(&bytes[..end]).chain_err(|| "model name isn't utf-8")?;
+    ensure!(!name.is_empty(), "empty model name");
+
+    Ok(name.to_string())
+}
+
+pub fn init() {
+    unsafe {
+        Event_RegisterPluginMessage(
+            &mut *ptr::addr_of_mut!(NetEvents.PluginMessageReceived),
+            ptr::null_mut(),
+            Some(on_plugin_message),
+        );
+    }
+}
+
+pub fn free() {
+    unsafe {
+        Event_UnregisterPluginMessage(
+            &mut *ptr::addr_of_mut!(NetEvents.PluginMessageReceived),
+            ptr::null_mut(),
+            Some(on_plugin_message),
+        );
+    }
+
+    RECEIVER.with(|cell| *cell.borrow_mut() = Receiver::default());
+    LOADED.with(|cell| cell.borrow_mut().clear());
+}
+
+/// Drops everything the last server gave us.
+pub fn reset() {
+    RECEIVER.with(|cell| *cell.borrow_mut() = Receiver::default());
+    downloads::free();
+
+    let loaded = LOADED.with(|cell| cell.borrow_mut().split_off(0));
+    for name in loaded {
+        unregister(&name);
+    }
+}
+
+extern "C" fn on_plugin_message(_obj: *mut c_void, channel: u8, data: *mut u8) {
+    if channel != CHANNEL {
+        return;
+    }
+
+    let message = unsafe { &*(data as *const [u8; MESSAGE_SIZE]) };
+    let received = RECEIVER.with(|cell| cell.borrow_mut().receive(message));
+
+    match received {
+        Ok(Some(Received::Use { hash, name })) => match Cache::default().get(&hash) {
+            Some(data) => load(&name, &hash, &data),
+            None => {
+                debug!("asking server for {:?} ({})", name, cache::to_hex(&hash));
+                send_missing(&hash);
+            }
+        },
+
+        Ok(Some(Received::Model { name, data })) => match Cache::default().put(&data) {
+            Ok(hash) => load(&name, &hash, &data),
+            Err(e) => report_error(Path::new(&name), &e),
+        },
+
+        Ok(Some(Received::Downloads(list))) => downloads::start(list),
+
+        Ok(None) => {}
+
+        Err(e) => report_error(Path::new("server"), &e),
+    }
+}
+
+fn send_missing(hash: &Hash) {
+    let mut message = [0; MESSAGE_SIZE];
+    message[0] = MISSING;
+    message[1..33].copy_from_slice(hash);
+
+    unsafe {
+        CPE_SendPluginMessage(CHANNEL, message.as_mut_ptr());
+    }
+}
+
+/// Servers can't replace the game's own models, and local ones already
+/// have their names claimed.
+fn check_name(name: &str) -> Result<()> {
+    let ours = model::names()
+        .iter()
+        .any(|ours| ours.eq_ignore_ascii_case(name));
+    let built_in = !ours && unsafe { !Model_Get(&OwnedString::new(name).as_cc_string()).is_null() };
+    ensure!(!built_in, "model name {:?} is built into the game", name);
+
+    Ok(())
+}
+
+/// Registers a model the server sent or told us to download.
+pub fn load(name: &str, hash: &Hash, data: &[u8]) {
+    debug!("loading server model {:?}", name);
+
+    let path = Cache::default().path(hash);
+    if let Err(e) = check_name(name) {
+        return report_error(&path, &e);
+    }
+
+    // the server may be replacing its own model with a new version
+    let replacing = LOADED.with(|cell| cell.borrow().iter().any(|loaded| loaded == name));
+    if replacing {
+        files::release(name);
+    }
+
+    if let Err(e) = files::claim_name(name, &path) {
+        return report_error(&path, &e);
+    }
+    if !replacing {
+        LOADED.with(|cell| cell.borrow_mut().push(name.to_string()));
+    }
+
+    worker::send(Job::Data {
+        name: name.to_string(),
+        source: path,
+        data: data.to_vec(),
+        sidecar: None,
+    });
+}
+
+/// What a server would send for `data`, for testing.
+#[cfg(test)]
+fn send_model(name: &str, data: &[u8]) -> Vec<[u8; MESSAGE_SIZE]> {
+    send_transfer(START, name, data)
+}
+
+#[cfg(test)]
+fn send_transfer(kind: u8, name: &str, data: &[u8]) -> Vec<[u8; MESSAGE_SIZE]> {
+    let mut start = [0; MESSAGE_SIZE];
+    start[0] = kind;
+    start[1..5].copy_from_slice(&(data.len() as u32).to_be_bytes());
+    start[5..5 + name.len()].copy_from_slice(name.as_bytes());
+
+    let mut messages = vec![start];
+    for chunk in data.chunks(MESSAGE_SIZE - 1) {
+        let mut message = [0; MESSAGE_SIZE];
+        message[0] = DATA;
+        message[1..1 + chunk.len()].copy_from_slice(chunk);
+        messages.push(message);
+    }
+
+    messages
+}
+
+#[test]
+fn test_receiver() {
+    let data = std::fs::read("tests/animated.bbmodel").unwrap();
+    let mut receiver = Receiver::default();
+
+    let mut received = Vec::new();
+    for message in send_model("mobs/slime", &data) {
+        received.extend(receiver.receive(&message).unwrap());
+    }
+    assert_eq!(
+        received,
+        vec![Received::Model {
+            name: "mobs/slime".to_string(),
+            data: data.clone(),
+        }]
+    );
+    super::blockbench::Blockbench::parse_bbmodel(&data).unwrap();
+
+    let mut use_message = [0; MESSAGE_SIZE];
+    use_message[0] = USE;
+    use_message[1..33].copy_from_slice(&cache::hash(&data));
+    use_message[33..38].copy_from_slice(b"slime");
+    assert_eq!(
+        receiver.receive(&use_message).unwrap(),
+        Some(Received::Use {
+            hash: cache::hash(&data),
+            name: "slime".to_string(),
+        })
+    );
+
+    // an empty model is done as soon as it starts
+    assert_eq!(
+        receiver.receive(&send_model("empty", b"")[0]).unwrap(),
+        Some(Received::Model {
+            name: "empty".to_string(),
+            data: Vec::new(),
+        })
+    );
+
+    let mut data_message = [0; MESSAGE_SIZE];
+    data_message[0] = DATA;
+    assert!(receiver.receive(&data_message).is_err());
+
+    let mut too_big = send_model("huge", b"")[0];
+    too_big[1..5].copy_from_slice(&(MAX_LENGTH as u32 + 1).to_be_bytes());
+    assert!(receiver.receive(&too_big).is_err());
+
+    assert!(receiver.receive(&[0xff; MESSAGE_SIZE]).is_err());
+
+    let hash = cache::to_hex(&cache::hash(&data));
+    let list = format!("slime http://localhost/slime.bbmodel {}\n", hash);
+    let mut received = Vec::new();
+    for message in send_transfer(URLS, "", list.as_bytes()) {
+        received.extend(receiver.receive(&message).unwrap());
+    }
+    assert_eq!(
+        received,
+        vec![Received::Downloads(Download::parse_list(&list).unwrap())]
+    );
+}
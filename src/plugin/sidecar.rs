@@ -0,0 +1,137 @@
+//! Optional `foo.toml` or `foo.json` next to `foo.bbmodel` with settings
+//! Blockbench can't express, overriding the bbmodel's own.
+
+use super::model::ModelOptions;
+use crate::error::*;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    os::raw::c_float,
+    path::{Path, PathBuf},
+};
+
+pub const EXTENSIONS: [&str; 2] = ["toml", "json"];
+
+/// Lengths are in pixels like in Blockbench.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sidecar {
+    pub scale: Option<c_float>,
+    pub offset: Option<[c_float; 3]>,
+    pub name_y: Option<c_float>,
+    pub eye_y: Option<c_float>,
+    pub head_group: Option<String>,
+    pub uses_skin: Option<bool>,
+    pub collision_size: Option<[c_float; 3]>,
+
+    /// alias to animation name
+    pub animations: Option<HashMap<String, String>>,
+}
+
+impl Sidecar {
+    /// The sidecar file for `bbmodel_path`, if there is one.
+    pub fn find(bbmodel_path: &Path) -> Option<PathBuf> {
+        EXTENSIONS
+            .iter()
+            .map(|extension| bbmodel_path.with_extension(extension))
+            .find(|path| path.is_file())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+
+        Self::parse(&data, path.extension() == Some(OsStr::new("json")))
+    }
+
+    /// toml unless `json`
+    pub fn parse(data: &str, json: bool) -> Result<Self> {
+        let sidecar: Self = if json {
+            serde_json::from_str(data)?
+        } else {
+            toml::from_str(data)?
+        };
+
+        if let Some(scale) = sidecar.scale {
+            ensure!(scale > 0.0, "scale {} must be above 0", scale);
+        }
+
+        Ok(sidecar)
+    }
+
+    pub fn apply(&self, options: &mut ModelOptions) {
+        if let Some(scale) = self.scale {
+            options.scale = scale;
+        }
+        if let Some(offset) = self.offset {
+            options.offset = [offset[0] / 16.0, offset[1] / 16.0, offset[2] / 16.0];
+        }
+        if let Some(name_y) = self.name_y {
+            options.name_y = Some(name_y / 16.0);
+        }
+        if let Some(eye_y) = self.eye_y {
+            options.eye_y = Some(eye_y / 16.0);
+        }
+        if let Some(head_group) = &self.head_group {
+            options.head_group = head_group.clone();
+        }
+        if let Some(uses_skin) = self.uses_skin {
+            options.uses_skin = uses_skin;
+        }
+        if let Some(collision_size) = self.collision_size {
+            options.collision_size = Some(collision_size);
+        }
+        if let Some(animations) = &self.animations {
+            options.animations = animations
+                .iter()
+                .map(|(alias, name)| (alias.to_lowercase(), name.clone()))
+                .collect();
+        }
+    }
+}
+
+#[test]
+fn test_sidecar() {
+    use crate::plugin::temp_dir::TempDir;
+
+    let dir = TempDir::new("sidecar");
+    let bbmodel = dir.join("boss.bbmodel");
+
+    assert_eq!(Sidecar::find(&bbmodel), None);
+
+    fs::write(
+        dir.join("boss.toml"),
+        "scale = 3.0\noffset = [0, 8, 0]\neye_y = 80\nhead_group = \"skull\"\nuses_skin = false\n",
+    )
+    .unwrap();
+    let path = Sidecar::find(&bbmodel).unwrap();
+    let sidecar = Sidecar::load(&path).unwrap();
+
+    let mut options = ModelOptions::default();
+    sidecar.apply(&mut options);
+    assert_eq!(options.scale, 3.0);
+    assert_eq!(options.offset, [0.0, 0.5, 0.0]);
+    assert_eq!(options.eye_y, Some(5.0));
+    assert_eq!(options.name_y, None);
+    assert_eq!(options.head_group, "skull");
+    assert!(!options.uses_skin);
+    assert!(options.animations.is_empty());
+
+    fs::remove_file(&path).unwrap();
+    fs::write(
+        dir.join("boss.json"),
+        r#"{ "collision_size": [16, 48, 16], "animations": { "Walk": "animation.boss.walk" } }"#,
+    )
+    .unwrap();
+    let sidecar = Sidecar::load(&Sidecar::find(&bbmodel).unwrap()).unwrap();
+    assert_eq!(sidecar.collision_size, Some([16.0, 48.0, 16.0]));
+
+    let mut options = ModelOptions::default();
+    sidecar.apply(&mut options);
+    assert_eq!(options.animations["walk"], "animation.boss.walk");
+
+    // typos shouldn't be silently ignored
+    fs::write(dir.join("boss.json"), r#"{ "scael": 2 }"#).unwrap();
+    assert!(Sidecar::load(&dir.join("boss.json")).is_err());
+}
@@ -0,0 +1,62 @@
+//! Folders for tests that touch the file system.
+
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// A new empty folder in the system's temp dir, deleted when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `name` just makes it easier to tell whose it is,
+    /// tests running at the same time still get their own.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "blockbench-loader-test-{}-{}-{}",
+            name,
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn test_temp_dir() {
+    let a = TempDir::new("temp-dir");
+    let b = TempDir::new("temp-dir");
+    assert_ne!(a.path(), b.path());
+    assert!(a.is_dir());
+
+    let path = a.to_path_buf();
+    fs::write(a.join("file"), "").unwrap();
+    drop(a);
+    assert!(!path.exists());
+}
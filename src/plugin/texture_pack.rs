@@ -0,0 +1,141 @@
+//! Models in the `blockbench/` folder of the texture pack in use,
+//! the server's or else the selected one, reloaded whenever the pack changes.
+
+use super::{
+    archive,
+    loader::{load_archived, report_error, unregister},
+};
+use classicube_sys::{
+    Event_RegisterVoid, Event_UnregisterVoid, Options_Get, OwnedString, TextureEvents,
+    TexturePack_Url, Utils_CRC32, STRING_SIZE,
+};
+use log::*;
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::Read,
+    os::raw::{c_char, c_void},
+    path::{Path, PathBuf},
+    ptr, slice,
+};
+
+const FOLDER: &str = "blockbench";
+
+thread_local!(
+    /// names of the models loaded from the current pack
+    static LOADED: RefCell<Vec<String>> = Default::default();
+);
+
+pub fn init() {
+    unsafe {
+        Event_RegisterVoid(
+            &mut *ptr::addr_of_mut!(TextureEvents.PackChanged),
+            ptr::null_mut(),
+            Some(on_pack_changed),
+        );
+    }
+
+    load();
+}
+
+pub fn free() {
+    unsafe {
+        Event_UnregisterVoid(
+            &mut *ptr::addr_of_mut!(TextureEvents.PackChanged),
+            ptr::null_mut(),
+            Some(on_pack_changed),
+        );
+    }
+
+    LOADED.with(|cell| cell.borrow_mut().clear());
+}
+
+extern "C" fn on_pack_changed(_obj: *mut c_void) {
+    load();
+}
+
+/// The server's pack if it sent one, else the selected one.
+fn pack_path() -> PathBuf {
+    let url = unsafe {
+        let url = &*ptr::addr_of!(TexturePack_Url);
+        if url.buffer.is_null() {
+            &[]
+        } else {
+            slice::from_raw_parts(url.buffer as *const u8, url.length as usize)
+        }
+    };
+
+    if url.is_empty() {
+        selected_pack_path()
+    } else {
+        server_pack_path(url)
+    }
+}
+
+/// Where the game keeps the pack downloaded from `url`, named by its CRC32 like
+/// `texturecache/<Utils_CRC32(url)>`.
+fn server_pack_path(url: &[u8]) -> PathBuf {
+    let crc = unsafe { Utils_CRC32(url.as_ptr(), url.len() as _) };
+    PathBuf::from("texturecache").join(crc.to_string())
+}
+
+/// `texpacks/default.zip` unless another pack was chosen.
+fn selected_pack_path() -> PathBuf {
+    let key = OwnedString::new("defaulttexpack");
+    let default = OwnedString::new("default.zip");
+
+    let mut buffer = [0 as c_char; STRING_SIZE as usize];
+    let mut value = classicube_sys::String {
+        buffer: buffer.as_mut_ptr(),
+        length: 0,
+        capacity: STRING_SIZE as _,
+    };
+
+    let name = unsafe {
+        Options_Get(
+            key.as_cc_string().buffer,
+            &mut value,
+            default.as_cc_string().buffer,
+        );
+        let bytes = slice::from_raw_parts(value.buffer as *const u8, value.length as usize);
+        String::from_utf8_lossy(bytes).to_string()
+    };
+
+    PathBuf::from("texpacks").join(name)
+}
+
+fn load() {
+    // the old pack's models go, their names are free for anything else now
+    let old_names: Vec<String> = LOADED.with(|cell| cell.borrow_mut().drain(..).collect());
+    for name in old_names {
+        unregister(&name);
+    }
+
+    let path = pack_path();
+    // servers can send just a terrain.png too
+    if !is_zip(&path) {
+        return;
+    }
+
+    debug!("loading models from texture pack {:?}", path);
+    match archive::read_models(&path, Some(FOLDER)) {
+        Ok(models) => LOADED.with(|cell| {
+            let loaded = &mut *cell.borrow_mut();
+            for model in models {
+                let name = model.name.clone();
+                if load_archived(&name, model) {
+                    loaded.push(name);
+                }
+            }
+        }),
+        Err(e) => report_error(&path, &e),
+    }
+}
+
+fn is_zip(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .map(|()| &magic == b"PK\x03\x04")
+        .unwrap_or(false)
+}
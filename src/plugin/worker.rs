@@ -0,0 +1,317 @@
+//! Reads and parses models on a background thread so the game doesn't freeze,
+//! the tick handler only registers what's finished.
+
+use super::{
+    blockbench::Blockbench,
+    cache::{self, Hash},
+    loader::report_error,
+    model::ModelOptions,
+    parse_cache,
+    sidecar::Sidecar,
+};
+use crate::error::*;
+use log::*;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+thread_local!(
+    static WORKER: RefCell<Option<Worker>> = Default::default();
+);
+
+struct Worker {
+    /// numbered in the order they were sent
+    jobs: Sender<(u64, Job)>,
+    parsed: Receiver<(u64, Parsed)>,
+    thread: JoinHandle<()>,
+
+    sent: u64,
+    /// name to the number of the last `Forget` sent for it,
+    /// anything parsed from jobs sent before that is thrown away
+    forgotten: HashMap<String, u64>,
+}
+
+/// The model's name must already be claimed.
+pub enum Job {
+    /// a .bbmodel file, with its sidecar file if there is one
+    File { name: String, path: PathBuf },
+
+    /// already read, like from an archive or the server
+    Data {
+        name: String,
+        /// where it came from, for errors
+        source: PathBuf,
+        data: Vec<u8>,
+        sidecar: Option<Sidecar>,
+    },
+
+    /// so `name` is parsed again next time even if it didn't change
+    Forget { name: String },
+
+    /// `tick` registered `name` from this
+    Registered {
+        name: String,
+        hash: Hash,
+        sidecar: Option<Sidecar>,
+    },
+}
+
+struct Parsed {
+    name: String,
+    source: PathBuf,
+
+    /// `None` if it didn't change since it was last registered
+    result: Result<Option<Loaded>>,
+}
+
+struct Loaded {
+    bb: Blockbench,
+    options: ModelOptions,
+    hash: Hash,
+    sidecar: Option<Sidecar>,
+}
+
+pub fn start(disk_cache: bool) {
+    let (jobs, job_receiver) = channel();
+    let (parsed_sender, parsed) = channel();
+
+    let thread = thread::spawn(move || {
+        for (number, job) in job_receiver {
+            if let Some(parsed) = parse(job, disk_cache) {
+                if parsed_sender.send((number, parsed)).is_err() {
+                    break;
+                }
+            }
+        }
+
+        parse_cache::free();
+    });
+
+    WORKER.with(|cell| {
+        *cell.borrow_mut() = Some(Worker {
+            jobs,
+            parsed,
+            thread,
+            sent: 0,
+            forgotten: HashMap::new(),
+        });
+    });
+}
+
+pub fn send(job: Job) {
+    WORKER.with(|cell| match &mut *cell.borrow_mut() {
+        Some(worker) => {
+            worker.sent += 1;
+            if let Job::Forget { name } = &job {
+                worker.forgotten.insert(name.clone(), worker.sent);
+            }
+
+            if worker.jobs.send((worker.sent, job)).is_err() {
+                warn!("model worker stopped");
+            }
+        }
+        None => warn!("model worker isn't running"),
+    });
+}
+
+/// Registers the models that finished parsing,
+/// unless they were unregistered after being sent.
+pub fn tick() {
+    let parsed: Vec<Parsed> = WORKER.with(|cell| match &*cell.borrow() {
+        Some(worker) => worker
+            .parsed
+            .try_iter()
+            .filter(|(number, parsed)| {
+                let stale = worker
+                    .forgotten
+                    .get(&parsed.name)
+                    .map(|forgotten| number < forgotten)
+                    .unwrap_or(false);
+                if stale {
+                    debug!("{:?} was unregistered while parsing", parsed.name);
+                }
+                !stale
+            })
+            .map(|(_, parsed)| parsed)
+            .collect(),
+        None => Vec::new(),
+    });
+
+    for Parsed {
+        name,
+        source,
+        result,
+    } in parsed
+    {
+        match result {
+            Ok(Some(Loaded {
+                bb,
+                options,
+                hash,
+                sidecar,
+            })) => {
+                bb.register_model(&name, options);
+                send(Job::Registered {
+                    name,
+                    hash,
+                    sidecar,
+                });
+            }
+            Ok(None) => debug!("{:?} didn't change", name),
+            Err(e) => report_error(&source, &e),
+        }
+    }
+}
+
+/// Waits for the current job, throwing away the rest.
+pub fn stop() {
+    let worker = WORKER.with(|cell| cell.borrow_mut().take());
+
+    if let Some(Worker {
+        jobs,
+        parsed,
+        thread,
+        ..
+    }) = worker
+    {
+        drop(jobs);
+        drop(parsed);
+        if thread.join().is_err() {
+            warn!("model worker panicked");
+        }
+    }
+}
+
+fn parse(job: Job, disk_cache: bool) -> Option<Parsed> {
+    let (name, source, result) = match job {
+        Job::File { name, path } => {
+            let result = read_file(&path)
+                .and_then(|(data, sidecar)| parse_data(&name, &data, sidecar, disk_cache));
+            (name, path, result)
+        }
+
+        Job::Data {
+            name,
+            source,
+            data,
+            sidecar,
+        } => {
+            let result = parse_data(&name, &data, sidecar, disk_cache);
+            (name, source, result)
+        }
+
+        Job::Forget { name } => {
+            parse_cache::forget(&name);
+            return None;
+        }
+
+        Job::Registered {
+            name,
+            hash,
+            sidecar,
+        } => {
+            parse_cache::registered(&name, hash, sidecar);
+            return None;
+        }
+    };
+
+    Some(Parsed {
+        name,
+        source,
+        result,
+    })
+}
+
+fn read_file(path: &Path) -> Result<(Vec<u8>, Option<Sidecar>)> {
+    debug!("opening file {:?}", path);
+    let data = fs::read(path)?;
+
+    let sidecar = Sidecar::find(path).and_then(|sidecar_path| {
+        debug!("loading sidecar {:?}", sidecar_path);
+        Sidecar::load(&sidecar_path)
+            .map_err(|e| warn!("{:?}: {}", sidecar_path, e))
+            .ok()
+    });
+
+    Ok((data, sidecar))
+}
+
+fn parse_data(
+    name: &str,
+    data: &[u8],
+    sidecar: Option<Sidecar>,
+    disk_cache: bool,
+) -> Result<Option<Loaded>> {
+    // editors often save twice
+    let hash = cache::hash(data);
+    if parse_cache::is_unchanged(name, &hash, sidecar.as_ref()) {
+        return Ok(None);
+    }
+
+    let bb = parse_cache::parse(&hash, data, disk_cache)?;
+
+    let mut options = bb.options();
+    if let Some(sidecar) = &sidecar {
+        sidecar.apply(&mut options);
+    }
+
+    Ok(Some(Loaded {
+        bb,
+        options,
+        hash,
+        sidecar,
+    }))
+}
+
+#[test]
+fn test_worker() {
+    let data = fs::read("tests/animated.bbmodel").unwrap();
+    let job = |data: &[u8]| Job::Data {
+        name: "animated".to_string(),
+        source: PathBuf::from("tests/animated.bbmodel"),
+        data: data.to_vec(),
+        sidecar: None,
+    };
+
+    let parsed = parse(job(&data), false).unwrap();
+    assert_eq!(parsed.name, "animated");
+    let loaded = parsed.result.unwrap().unwrap();
+
+    // not registered yet
+    assert!(parse(job(&data), false).unwrap().result.unwrap().is_some());
+
+    let registered = Job::Registered {
+        name: "animated".to_string(),
+        hash: loaded.hash,
+        sidecar: loaded.sidecar,
+    };
+    assert!(parse(registered, false).is_none());
+
+    // saved again without changes
+    assert!(parse(job(&data), false).unwrap().result.unwrap().is_none());
+
+    let forget = Job::Forget {
+        name: "animated".to_string(),
+    };
+    assert!(parse(forget, false).is_none());
+    assert!(parse(job(&data), false).unwrap().result.unwrap().is_some());
+
+    assert!(parse(job(b"{}"), false).unwrap().result.is_err());
+
+    let parsed = parse(
+        Job::File {
+            name: "missing".to_string(),
+            path: PathBuf::from("tests/missing.bbmodel"),
+        },
+        false,
+    )
+    .unwrap();
+    assert!(parsed.result.is_err());
+
+    parse_cache::free();
+}